@@ -1,6 +1,24 @@
 use crate::image::*;
 use crate::image::blend::BlendMode;
+use crate::image::channel::{ Channel, Swizzle };
+use crate::image::compound::{ DropShadow, Glow };
+use crate::image::effect::{ GradientOp, GradientOutput, Normalize };
+use crate::image::filter::Kuwahara;
+use crate::image::lut::{ Lut, LutInterp };
+use crate::image::mask::MaskSource;
+use crate::image::morph::{ Element, MorphOp, MorphTarget };
+use crate::image::path::Path;
+use crate::image::quantize::{ Dither, PaletteMethod };
+use crate::image::raster::{ self, FillRule, LineCap, LineJoin, Stroke };
+use crate::image::resample::Kernel;
+use crate::image::stylize::{ Pixelate, Screen };
+use crate::image::text::{ Align, TextStyle };
+use crate::image::transform::{ Bounds, Filter, Flip, Gravity, fit_points };
+use crate::image::warp::Ripple;
+use crate::expr::{ self, Expr, Name };
 use crate::parse::{ List, Spanned };
+use image::Rgba;
+use ndarray::Array2;
 use std::collections::HashMap;
 
 #[derive(Clone, Debug)]
@@ -9,18 +27,185 @@ pub enum DataType {
     Number(f64),
     Str(String),
     Sym(String),
+    Vec(Vec<DataType>),
     Image(Image),
+    Lut(Lut),
+    Path(Path),
 }
 
 impl DataType {
+    /// Flatten a vector of numbers, `None` if any element is not a number
+    pub fn as_numbers(&self) -> Option<Vec<f64>> {
+        match self {
+            DataType::Vec(xs) => xs.iter()
+                .map(|x| match x {
+                    DataType::Number(n) => Some(*n),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+
     pub fn type_name(&self) -> &'static str {
         match self {
             DataType::Nil => "nil",
             DataType::Number(_) => "number",
             DataType::Str(_) => "string",
             DataType::Sym(_) => "symbol",
+            DataType::Vec(_) => "vector",
             DataType::Image(_) => "image",
+            DataType::Lut(_) => "lut",
+            DataType::Path(_) => "path",
+        }
+    }
+}
+
+/// Colour from `[r g b]` / `[r g b a]` in `0..=255` or a `"#rrggbb"` /
+/// `"#rrggbbaa"` hex string
+pub fn to_color(value: &DataType) -> Option<Rgba<u8>> {
+    match value {
+        DataType::Str(s) => {
+            let hex = s.strip_prefix('#')?;
+            if !hex.is_ascii() || (hex.len() != 6 && hex.len() != 8) {
+                return None;
+            }
+            let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            let a = if hex.len() == 8 { byte(6)? } else { 255 };
+            Some(Rgba([byte(0)?, byte(2)?, byte(4)?, a]))
+        }
+        DataType::Vec(_) => {
+            let c = value.as_numbers()?;
+            let c = c.iter().map(|v| v.round().clamp(0.0, 255.0) as u8).collect::<Vec<_>>();
+            match c[..] {
+                [r, g, b] => Some(Rgba([r, g, b, 255])),
+                [r, g, b, a] => Some(Rgba([r, g, b, a])),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// List of points from `[[x y] [x y] ...]`
+pub fn to_points(value: &DataType) -> Option<Vec<[f64; 2]>> {
+    match value {
+        DataType::Vec(points) => points
+            .iter()
+            .map(|p| match p.as_numbers().as_deref() {
+                Some(&[x, y]) => Some([x, y]),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Row major matrix of `N` numbers from a vector of rows, e.g. an affine
+/// `[[a b c] [d e f]]`, or from a flat `[a b c d e f]`
+pub fn to_matrix<const N: usize>(value: &DataType) -> Option<[f64; N]> {
+    let values = match value {
+        DataType::Vec(rows) if rows.iter().all(|r| matches!(r, DataType::Vec(_))) => rows
+            .iter()
+            .map(|r| r.as_numbers())
+            .collect::<Option<Vec<_>>>()
+            .map(|rows| rows.concat()),
+        _ => value.as_numbers(),
+    };
+    values?.try_into().ok()
+}
+
+/// Most images `img-combine` walks at once, bounds the per pixel slots
+const MAX_PIXEL_IMAGES: usize = 8;
+
+/// Slots of the per pixel variables available to pixel expressions over
+/// `images` images: `r g b a` are the first image and `r1 g1 b1 a1`,
+/// `r2 ...` each numbered image
+fn pixel_slot(name: &str, images: usize) -> Option<usize> {
+    Some(match name {
+        "x" => 0,
+        "y" => 1,
+        "u" => 2,
+        "v" => 3,
+        "width" => 4,
+        "height" => 5,
+        _ => {
+            let mut chars = name.chars();
+            let c = match chars.next()? {
+                'r' => 0,
+                'g' => 1,
+                'b' => 2,
+                'a' => 3,
+                _ => return None,
+            };
+            let index = match chars.as_str() {
+                "" => 1,
+                n => n.parse::<usize>().ok().filter(|&i| (1..=images).contains(&i))?,
+            };
+            6 + (index - 1) * 4 + c
+        }
+    })
+}
+
+/// Fill the coordinate slots for pixel `(x, y)`, `u` and `v` run from 0 at
+/// the first pixel to 1 at the last
+fn pixel_coords(slots: &mut [f64], x: usize, y: usize, width: usize, height: usize) {
+    let (w, h) = (width as f64, height as f64);
+    slots[..6].copy_from_slice(&[
+        x as f64,
+        y as f64,
+        x as f64 / (w - 1.0).max(1.0),
+        y as f64 / (h - 1.0).max(1.0),
+        w,
+        h,
+    ]);
+}
+
+/// Compile the unevaluated, optionally quoted, output of a pixel
+/// expression: `[r g b a]`, `[r g b]` keeping alpha or one grey value.
+/// Numbers bound with `def` can be used as constants.
+fn compile_pixel<'a>(
+    env: &Env,
+    expr: &Spanned<List>,
+    slot: &dyn Fn(&str) -> Option<usize>,
+) -> Result<Vec<Expr>, chumsky::error::Rich<'a, String>> {
+    let resolve = |name: &str| match slot(name) {
+        Some(i) => Some(Name::Slot(i)),
+        None => match env.get(name) {
+            Some(DataType::Number(n)) => Some(Name::Constant(*n)),
+            _ => None,
+        },
+    };
+
+    let expr = match &expr.0 {
+        List::Quote(inner) => inner.as_ref(),
+        _ => expr,
+    };
+    match &expr.0 {
+        List::Vec(items) if items.len() == 3 || items.len() == 4 => items
+            .iter()
+            .map(|item| expr::compile(item, &resolve))
+            .collect(),
+        List::Vec(_) => Err(chumsky::error::Rich::custom(
+            expr.1,
+            "pixel expression must be [r g b], [r g b a] or a single grey value".to_string(),
+        )),
+        _ => Ok(vec![expr::compile(expr, &resolve)?]),
+    }
+}
+
+/// Evaluate compiled channels into a pixel, `alpha` is kept when there is
+/// no alpha expression
+fn eval_pixel(channels: &[Expr], slots: &[f64], alpha: u8) -> Rgba<u8> {
+    let c = |e: &Expr| e.eval(slots).round().clamp(0.0, 255.0) as u8;
+    match channels {
+        [v] => {
+            let v = c(v);
+            Rgba([v, v, v, alpha])
         }
+        [r, g, b] => Rgba([c(r), c(g), c(b), alpha]),
+        [r, g, b, a] => Rgba([c(r), c(g), c(b), c(a)]),
+        _ => unreachable!("checked when compiling"),
     }
 }
 
@@ -101,16 +286,6 @@ pub fn eval_expr<'a>(env: &mut Env, expr: Spanned<List>)
                 };
             }
 
-            macro_rules! next_or_default {
-                ($default:expr) => {
-                    if let Some(item) = iter.next() {
-                        item
-                    } else {
-                        return Ok($default);
-                    }
-                };
-            }
-
             let first = next_or!("missing function name");
 
             let (f, _) = first;
@@ -131,6 +306,150 @@ pub fn eval_expr<'a>(env: &mut Env, expr: Spanned<List>)
                 }};
             }
 
+            // Like `check!`, but falls back to `$default` when the argument is omitted
+            macro_rules! check_or {
+                ($name:ident, $type:ident, $default:expr) => {
+                    match iter.next() {
+                        Some(item) => check!($name, $type, eval_expr(env, item)?),
+                        None => $default,
+                    }
+                };
+            }
+
+            // Optional colour argument, see `to_color`
+            macro_rules! color_or {
+                ($name:ident, $default:expr) => {
+                    match iter.next() {
+                        Some(item) => {
+                            let $name = eval_expr(env, item)?;
+                            match to_color(&$name) {
+                                Some(c) => c,
+                                None => return err!(
+                                    "{} must be a colour ([r g b], [r g b a] or \"#rrggbb\"), got {}",
+                                    stringify!($name),
+                                    $name.type_name()
+                                ),
+                            }
+                        }
+                        None => $default,
+                    }
+                };
+            }
+
+            macro_rules! edge_mode {
+                ($name:ident, $default:expr) => {{
+                    let $name = check_or!($name, Sym, $default.to_string());
+                    match $name.as_str() {
+                        "transparent" | "zero" => EdgeMode::Transparent,
+                        "clamp" | "extend" => EdgeMode::Clamp,
+                        "mirror" | "reflect" => EdgeMode::Mirror,
+                        "wrap" | "repeat" | "tile" => EdgeMode::Wrap,
+                        _ => return err!("unknown edge mode: {}", $name),
+                    }
+                }};
+            }
+
+            // Optional resampling filter for geometric transforms
+            macro_rules! filter {
+                ($name:ident, $default:expr) => {{
+                    let $name = check_or!($name, Sym, $default.to_string());
+                    match $name.as_str() {
+                        "nearest" | "nearest-neighbor" | "nearest-neighbour" | "nn" => Filter::Nearest,
+                        "bilinear" | "b" => Filter::Bilinear,
+                        "box" | "area" => Filter::Kernel(Kernel::Box),
+                        "bicubic" | "cubic" | "catmull-rom" => Filter::Kernel(Kernel::CatmullRom),
+                        "mitchell" => Filter::Kernel(Kernel::Mitchell),
+                        "lanczos2" => Filter::Kernel(Kernel::Lanczos2),
+                        "lanczos" | "lanczos3" => Filter::Kernel(Kernel::Lanczos3),
+                        _ => return err!("unknown filter: {}", $name),
+                    }
+                }};
+            }
+
+            macro_rules! bounds {
+                ($name:ident, $default:expr) => {{
+                    let $name = check_or!($name, Sym, $default.to_string());
+                    match $name.as_str() {
+                        "fixed" | "keep" | "crop" => Bounds::Fixed,
+                        "expand" | "fit" => Bounds::Expand,
+                        _ => return err!("unknown bounds: {}", $name),
+                    }
+                }};
+            }
+
+            macro_rules! points {
+                ($name:ident, $expr:expr) => {{
+                    let $name = $expr;
+                    match to_points(&$name) {
+                        Some(points) => points,
+                        None => return err!(
+                            "{} must be a vector of [x y] points, got {}",
+                            stringify!($name),
+                            $name.type_name()
+                        ),
+                    }
+                }};
+            }
+
+            macro_rules! fill_rule {
+                ($name:ident) => {{
+                    let $name = check_or!($name, Sym, "nonzero".to_string());
+                    match $name.as_str() {
+                        "nonzero" | "non-zero" => FillRule::NonZero,
+                        "evenodd" | "even-odd" => FillRule::EvenOdd,
+                        _ => return err!("unknown fill rule: {}", $name),
+                    }
+                }};
+            }
+
+            macro_rules! point {
+                ($name:ident, $msg:expr) => {{
+                    let item = next_or!($msg);
+                    let $name = eval_expr(env, item)?;
+                    match $name.as_numbers().as_deref() {
+                        Some(&[x, y]) => [x, y],
+                        _ => return err!(
+                            "{} must be a vector of two numbers, got {}",
+                            stringify!($name),
+                            $name.type_name()
+                        ),
+                    }
+                }};
+            }
+
+            // Optional part of an image read as a mask, luminance by default
+            macro_rules! mask_source {
+                ($name:ident) => {{
+                    let $name = check_or!($name, Sym, "luma".to_string());
+                    match $name.as_str() {
+                        "luma" | "luminance" | "grey" | "gray" => MaskSource::Luma,
+                        "alpha" => MaskSource::Alpha,
+                        _ => return err!("unknown mask source: {}", $name),
+                    }
+                }};
+            }
+
+            // Optional join and cap for strokes
+            macro_rules! stroke_style {
+                ($width:expr) => {{
+                    let join = check_or!(join, Sym, "miter".to_string());
+                    let cap  = check_or!(cap, Sym, "butt".to_string());
+                    let join = match join.as_str() {
+                        "miter" => LineJoin::Miter,
+                        "round" => LineJoin::Round,
+                        "bevel" => LineJoin::Bevel,
+                        _ => return err!("unknown line join: {}", join),
+                    };
+                    let cap = match cap.as_str() {
+                        "butt" => LineCap::Butt,
+                        "round" => LineCap::Round,
+                        "square" => LineCap::Square,
+                        _ => return err!("unknown line cap: {}", cap),
+                    };
+                    Stroke { width: $width, join, cap, miter_limit: 4.0 }
+                }};
+            }
+
             match f {
                 List::Sym("canvas") => {
                     let width  = next_or!("missing width for `canvas`");
@@ -147,6 +466,23 @@ pub fn eval_expr<'a>(env: &mut Env, expr: Spanned<List>)
                     }
                 }
 
+                List::Sym("img-new") => {
+                    // (img-new w h color)
+                    let width  = next_or!("missing width for `new`");
+                    let height = next_or!("missing height for `new`");
+                    let width  = check!(width, Number, eval_expr(env, width)?);
+                    let height = check!(height, Number, eval_expr(env, height)?);
+                    let color  = color_or!(color, Rgba([0, 0, 0, 0]));
+
+                    if width < 0.0 || height < 0.0 {
+                        return err!("image size cannot be negative");
+                    }
+
+                    let mut new_image = Image::new(width as usize, height as usize);
+                    new_image.image.fill(color);
+                    Ok(DataType::Image(new_image))
+                }
+
                 List::Sym("img-load") => {
                     let path = next_or!("missing path for `load`");
                     let path = eval_expr(env, path)?;
@@ -167,7 +503,7 @@ pub fn eval_expr<'a>(env: &mut Env, expr: Spanned<List>)
                     let image = eval_expr(env, image)?;
                     if let DataType::Image(img) = image {
                         if let Some(canvas) = env.canvas_mut() {
-                            canvas.blend_images(&img, BlendMode::Normal);
+                            canvas.blend_images(&img, BlendMode::Normal, None);
                             Ok(DataType::Nil)
                         } else {
                             err!("no canvas defined")
@@ -178,25 +514,111 @@ pub fn eval_expr<'a>(env: &mut Env, expr: Spanned<List>)
                 }
 
                 List::Sym("img-resize") => {
+                    // (img-resize image method w h edge)
+                    // (img-resize image method 'fit|'fill w h gravity edge)
+                    // (img-resize image method 'fit-width w edge)
+                    // (img-resize image method 'fit-height h edge)
+                    // (img-resize image method 'scale factor edge)
                     let image  = next_or!("missing image for `resize`");
                     let method = next_or!("missing resize method for `resize`");
-                    let width  = next_or!("missing width for `resize`");
-                    let height = next_or!("missing height for `resize`");
+                    let size   = next_or!("missing width or mode for `resize`");
                     let image  = check!(image, Image, eval_expr(env, image)?);
                     let method = check!(method, Sym, eval_expr(env, method)?);
-                    let width  = check!(width, Number, eval_expr(env, width)?);
-                    let height = check!(height, Number, eval_expr(env, height)?);
+
+                    let (src_width, src_height) = (image.width as f64, image.height as f64);
+                    let scaled = |s: f64| (
+                        (src_width * s).round().max(1.0),
+                        (src_height * s).round().max(1.0),
+                    );
+
+                    // size to resize to, and the frame it is then cropped or padded to
+                    let (width, height, frame) = match eval_expr(env, size)? {
+                        DataType::Number(width) => {
+                            let height = next_or!("missing height for `resize`");
+                            let height = check!(height, Number, eval_expr(env, height)?);
+                            (width, height, None)
+                        }
+                        DataType::Sym(mode) => match mode.as_str() {
+                            "fit" | "letterbox" | "fill" | "cover" => {
+                                let width   = next_or!("missing width for `resize`");
+                                let height  = next_or!("missing height for `resize`");
+                                let width   = check!(width, Number, eval_expr(env, width)?);
+                                let height  = check!(height, Number, eval_expr(env, height)?);
+                                let gravity = check_or!(gravity, Sym, "center".to_string());
+                                let gravity = match gravity.as_str() {
+                                    "center" | "centre" | "middle" => Gravity::Center,
+                                    "top" | "north" => Gravity::Top,
+                                    "bottom" | "south" => Gravity::Bottom,
+                                    "left" | "west" => Gravity::Left,
+                                    "right" | "east" => Gravity::Right,
+                                    "top-left" | "north-west" => Gravity::TopLeft,
+                                    "top-right" | "north-east" => Gravity::TopRight,
+                                    "bottom-left" | "south-west" => Gravity::BottomLeft,
+                                    "bottom-right" | "south-east" => Gravity::BottomRight,
+                                    _ => return err!("unknown gravity: {}", gravity),
+                                };
+
+                                let (sx, sy) = (width / src_width, height / src_height);
+                                let s = if matches!(mode.as_str(), "fit" | "letterbox") {
+                                    sx.min(sy)
+                                } else {
+                                    sx.max(sy)
+                                };
+                                let (w, h) = scaled(s);
+                                (w, h, Some((width, height, gravity)))
+                            }
+                            "fit-width" => {
+                                let width = next_or!("missing width for `resize`");
+                                let width = check!(width, Number, eval_expr(env, width)?);
+                                let (_, h) = scaled(width / src_width);
+                                (width, h, None)
+                            }
+                            "fit-height" => {
+                                let height = next_or!("missing height for `resize`");
+                                let height = check!(height, Number, eval_expr(env, height)?);
+                                let (w, _) = scaled(height / src_height);
+                                (w, height, None)
+                            }
+                            "scale" | "factor" => {
+                                let factor = next_or!("missing factor for `resize`");
+                                let factor = check!(factor, Number, eval_expr(env, factor)?);
+                                let (w, h) = scaled(factor);
+                                (w, h, None)
+                            }
+                            _ => return err!("unknown resize mode: {}", mode),
+                        },
+                        other => return err!(
+                            "width must be of type Number or Sym, got {}", other.type_name()),
+                    };
+                    let edge   = edge_mode!(edge, "clamp");
+
+                    if width <= 0.0 || height <= 0.0 {
+                        return err!("resize dimensions must be positive");
+                    }
+                    let (width, height) = (width as usize, height as usize);
 
                     let mut resized = image.clone();
                     match method.as_str() {
                         "nearest"
                         | "nearest-neighbor"
                         | "nearest-neighbour"
-                        | "nn" => resized.resize_nearest_neighbour(width as usize, height as usize),
+                        | "nn" => resized.resize_nearest_neighbour(width, height, edge),
                         "bilinear"
-                        | "b" => resized.resize_bilinear(width as usize, height as usize),
+                        | "b" => resized.resize_bilinear(width, height, edge),
+                        "box"
+                        | "area" => resized.resize_filtered(width, height, Kernel::Box, edge),
+                        "bicubic"
+                        | "cubic"
+                        | "catmull-rom" => resized.resize_filtered(width, height, Kernel::CatmullRom, edge),
+                        "mitchell" => resized.resize_filtered(width, height, Kernel::Mitchell, edge),
+                        "lanczos2" => resized.resize_filtered(width, height, Kernel::Lanczos2, edge),
+                        "lanczos"
+                        | "lanczos3" => resized.resize_filtered(width, height, Kernel::Lanczos3, edge),
                         _ => return err!("unknown resize method: {}", method),
                     }
+                    if let Some((width, height, gravity)) = frame {
+                        resized.frame(width as usize, height as usize, gravity);
+                    }
                     Ok(DataType::Image(resized))
                 }
 
@@ -204,12 +626,11 @@ pub fn eval_expr<'a>(env: &mut Env, expr: Spanned<List>)
                     let image = next_or!("missing image for `move`");
                     let x     = next_or!("missing x offset for `move`");
                     let y     = next_or!("missing y offset for `move`");
-                    let method = next_or_default!(DataType::Sym("pixels".to_string()));
 
                     let image = check!(image, Image, eval_expr(env, image)?);
                     let x     = check!(x, Number, eval_expr(env, x)?);
                     let y     = check!(y, Number, eval_expr(env, y)?);
-                    let method = check!(method, Sym, eval_expr(env, method)?);
+                    let method = check_or!(method, Sym, "pixels".to_string());
 
                     let mut new_image = image.clone();
                     // new_image.shift_with_empty(x as isize, y as isize);
@@ -223,128 +644,1733 @@ pub fn eval_expr<'a>(env: &mut Env, expr: Spanned<List>)
                     Ok(DataType::Image(new_image))
                 }
 
-                List::Sym("img-mix") => {
-                    // first = bottom, second = top
-                    let image_a = next_or!("missing first image for `mix`");
-                    let image_b = next_or!("missing second image for `mix`");
-                    let mode    = next_or!("missing blend mode for `mix`");
+                List::Sym("img-crop") => {
+                    // (img-crop image x y w h)
+                    let image  = next_or!("missing image for `crop`");
+                    let x      = next_or!("missing x for `crop`");
+                    let y      = next_or!("missing y for `crop`");
+                    let width  = next_or!("missing width for `crop`");
+                    let height = next_or!("missing height for `crop`");
 
-                    let image_a = check!(image_a, Image, eval_expr(env, image_a)?);
-                    let image_b = check!(image_b, Image, eval_expr(env, image_b)?);
-                    let mode    = check!(mode, Sym, eval_expr(env, mode)?);
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let x      = check!(x, Number, eval_expr(env, x)?);
+                    let y      = check!(y, Number, eval_expr(env, y)?);
+                    let width  = check!(width, Number, eval_expr(env, width)?);
+                    let height = check!(height, Number, eval_expr(env, height)?);
 
-                    let blend_mode = match mode.as_str() {
-                        "normal"   => BlendMode::Normal,
-                        "multiply" => BlendMode::Multiply,
-                        "screen"   => BlendMode::Screen,
-                        "overlay"  => BlendMode::Overlay,
-                        _ => return err!("unknown blend mode: {}", mode),
+                    if width < 0.0 || height < 0.0 {
+                        return err!("crop size cannot be negative");
+                    }
+
+                    let mut new_image = image.clone();
+                    new_image.crop(x.round() as isize, y.round() as isize, width as usize, height as usize);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-flip") => {
+                    // (img-flip image 'h|'v|'both)
+                    let image = next_or!("missing image for `flip`");
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let axis  = check_or!(axis, Sym, "horizontal".to_string());
+
+                    let flip = match axis.as_str() {
+                        "horizontal" | "h" | "x" => Flip::Horizontal,
+                        "vertical" | "v" | "y" => Flip::Vertical,
+                        "both" | "hv" | "xy" => Flip::Both,
+                        _ => return err!("unknown flip axis: {}", axis),
                     };
 
-                    let mut new_image = image_a.clone();
-                    new_image.blend_images(&image_b, blend_mode);
+                    let mut new_image = image.clone();
+                    new_image.flip(flip);
                     Ok(DataType::Image(new_image))
                 }
 
-                List::Sym("eff-blur") => {
-                    let image  = next_or!("missing image for `blur`");
-                    let radius = next_or!("missing radius for `blur`");
+                List::Sym("img-rotate") => {
+                    // (img-rotate image degrees 'expand|'fixed filter edge), clockwise
+                    let image   = next_or!("missing image for `rotate`");
+                    let degrees = next_or!("missing angle for `rotate`");
+
+                    let image   = check!(image, Image, eval_expr(env, image)?);
+                    let degrees = check!(degrees, Number, eval_expr(env, degrees)?);
+                    let bounds  = bounds!(bounds, "expand");
+                    let filter  = filter!(filter, "bilinear");
+                    let edge    = edge_mode!(edge, "transparent");
+
+                    let mut new_image = image.clone();
+                    new_image.rotate(degrees, bounds, filter, edge);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-scale") => {
+                    // (img-scale image factor|[sx sy] [px py] filter edge)
+                    let image  = next_or!("missing image for `scale`");
+                    let factor = next_or!("missing factor for `scale`");
 
                     let image  = check!(image, Image, eval_expr(env, image)?);
-                    let radius = check!(radius, Number, eval_expr(env, radius)?);
+                    let factor = eval_expr(env, factor)?;
+                    let (sx, sy) = match (&factor, factor.as_numbers().as_deref()) {
+                        (DataType::Number(s), _) => (*s, *s),
+                        (_, Some(&[sx, sy])) => (sx, sy),
+                        _ => return err!("scale factor must be a number or a vector of two numbers"),
+                    };
+                    let pivot  = check_or!(pivot, Vec, vec![
+                        DataType::Number(image.width as f64 / 2.0),
+                        DataType::Number(image.height as f64 / 2.0),
+                    ]);
+                    let filter = filter!(filter, "bilinear");
+                    let edge   = edge_mode!(edge, "transparent");
 
-                    if radius < 0.0 {
-                        return err!("blur radius cannot be negative");
+                    let (px, py) = match DataType::Vec(pivot).as_numbers().as_deref() {
+                        Some(&[px, py]) => (px, py),
+                        _ => return err!("pivot must be a vector of two numbers"),
+                    };
+
+                    let mut new_image = image.clone();
+                    if let Err(e) = new_image.scale(sx, sy, px, py, filter, edge) {
+                        return err!("failed to scale image: {}", e);
                     }
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-affine") => {
+                    // (img-affine image [[a b c] [d e f]] 'fixed|'expand filter edge)
+                    let image  = next_or!("missing image for `affine`");
+                    let matrix = next_or!("missing matrix for `affine`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let matrix = eval_expr(env, matrix)?;
+                    let Some(matrix) = to_matrix::<6>(&matrix) else {
+                        return err!("affine matrix must be [[a b c] [d e f]] or [a b c d e f]");
+                    };
+                    let bounds = bounds!(bounds, "fixed");
+                    let filter = filter!(filter, "bilinear");
+                    let edge   = edge_mode!(edge, "transparent");
 
                     let mut new_image = image.clone();
-                    new_image.blur(radius as usize);
+                    if let Err(e) = new_image.affine(&matrix, bounds, filter, edge) {
+                        return err!("failed to transform image: {}", e);
+                    }
                     Ok(DataType::Image(new_image))
                 }
 
-                List::Sym("def") => {
-                    let name  = next_or!("missing variable name for `def`");
-                    let value = next_or!("missing value for variable");
-                    let value = eval_expr(env, value)?;
-                    env.set(name.0.to_string(), value);
-                    Ok(DataType::Nil)
-                },
+                List::Sym("img-perspective") => {
+                    // (img-perspective image [[x y] [x y] [x y] [x y]] [w h] filter edge)
+                    // corners go top left, top right, bottom right, bottom left
+                    let image   = next_or!("missing image for `perspective`");
+                    let corners = next_or!("missing corners for `perspective`");
 
-                // (-> 1 (+ 2) ...) => (+ <1> 2)
-                List::Sym("->") => {
-                    let first = next_or!("missing first argument for `->`");
-                    let fns = iter.collect::<Vec<_>>();
+                    let image   = check!(image, Image, eval_expr(env, image)?);
+                    let corners = eval_expr(env, corners)?;
+                    let corners: [[f64; 2]; 4] = match to_points(&corners)
+                        .and_then(|points| points.try_into().ok())
+                    {
+                        Some(corners) => corners,
+                        None => return err!("corners must be a vector of four [x y] points"),
+                    };
+                    let (fit_width, fit_height) = fit_points(&corners);
+                    let size   = check_or!(size, Vec, vec![
+                        DataType::Number(fit_width as f64),
+                        DataType::Number(fit_height as f64),
+                    ]);
+                    let filter = filter!(filter, "bilinear");
+                    let edge   = edge_mode!(edge, "transparent");
 
-                    // if fns.is_empty() { return err!("empty `->`"); }
-                    if fns.is_empty() {
-                        return Ok(eval_expr(env, first)?);
+                    let (width, height) = match DataType::Vec(size).as_numbers().as_deref() {
+                        Some(&[w, h]) if w >= 0.0 && h >= 0.0 => (w as usize, h as usize),
+                        _ => return err!("size must be a vector of two positive numbers"),
+                    };
+
+                    let mut new_image = image.clone();
+                    if let Err(e) = new_image.perspective(&corners, width, height, filter, edge) {
+                        return err!("failed to warp image: {}", e);
                     }
+                    Ok(DataType::Image(new_image))
+                }
 
-                    let mut result_list = first;
+                List::Sym("img-mesh-warp") => {
+                    // (img-mesh-warp image [[[x y] [x y] ...] ...] [w h] filter edge)
+                    // one row of destination points per lattice row, evenly spaced over the source
+                    let image   = next_or!("missing image for `mesh-warp`");
+                    let lattice = next_or!("missing lattice for `mesh-warp`");
 
-                    // transform
-                    for (f, s) in fns.into_iter() {
-                        match f {
-                            List::Sym(_) => {
-                                // (-> 1 f) => (f <1>)
-                                result_list = (List::Cons(vec![(f, s), result_list]), s);
-                            },
-                            List::Cons(xs) if xs.is_empty() => {
-                                return err!("empty function in `->`");
-                            },
-                            List::Cons(xs) => {
-                                // (-> 1 (f 2)) => (f <1> 2)
-                                let mut xs = xs.into_iter();
-                                let mut vec = vec![xs.next().unwrap(), result_list];
-                                while let Some(item) = xs.next() {
-                                    vec.push(item);
-                                }
-                                result_list = (List::Cons(vec), s);
-                            },
-                            e => {
-                                return err!("{} is not supported in `->`", e);
-                            }
-                        }
+                    let image   = check!(image, Image, eval_expr(env, image)?);
+                    let lattice = check!(lattice, Vec, eval_expr(env, lattice)?);
+                    let rows = match lattice.iter().map(to_points).collect::<Option<Vec<_>>>() {
+                        Some(rows) => rows,
+                        None => return err!("lattice must be a vector of rows of [x y] points"),
+                    };
+                    let cols = rows.first().map_or(0, |row| row.len());
+                    if rows.len() < 2 || cols < 2 || rows.iter().any(|row| row.len() != cols) {
+                        return err!("lattice must have at least 2 rows of the same length, with at least 2 points each");
                     }
+                    let lattice = Array2::from_shape_vec((rows.len(), cols), rows.concat())
+                        .expect("rows have equal length");
 
-                    Ok(eval_expr(env, result_list)?)
-                },
+                    let (fit_width, fit_height) = fit_points(lattice.iter());
+                    let size   = check_or!(size, Vec, vec![
+                        DataType::Number(fit_width as f64),
+                        DataType::Number(fit_height as f64),
+                    ]);
+                    let filter = filter!(filter, "bilinear");
+                    let edge   = edge_mode!(edge, "transparent");
 
-                List::Sym("+")
-                | List::Sym("-")
-                | List::Sym("*")
-                | List::Sym("/")
-                | List::Sym("%") => {
-                    let a = next_or!("missing first argument for arithmetic operation");
-                    let b = next_or!("missing second argument for arithmetic operation");
+                    let (width, height) = match DataType::Vec(size).as_numbers().as_deref() {
+                        Some(&[w, h]) if w >= 0.0 && h >= 0.0 => (w as usize, h as usize),
+                        _ => return err!("size must be a vector of two positive numbers"),
+                    };
 
-                    let a = check!(a, Number, eval_expr(env, a)?);
-                    let b = check!(b, Number, eval_expr(env, b)?);
+                    let mut new_image = image.clone();
+                    new_image.mesh_warp(&lattice, width, height, filter, edge);
+                    Ok(DataType::Image(new_image))
+                }
 
-                    let result = match f {
-                        List::Sym("+") => a + b,
-                        List::Sym("-") => a - b,
-                        List::Sym("*") => a * b,
-                        List::Sym("/") => {
-                            if b == 0.0 {
-                                return err!("division by zero");
-                            }
-                            a / b
-                        },
-                        List::Sym("%") => {
-                            if b == 0.0 {
-                                return err!("division by zero");
-                            }
-                            a % b
-                        },
-                        _ => unreachable!(),
+                List::Sym("img-tile") => {
+                    // (img-tile image w h 'repeat|'mirror offset)
+                    let image  = next_or!("missing image for `tile`");
+                    let width  = next_or!("missing width for `tile`");
+                    let height = next_or!("missing height for `tile`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let width  = check!(width, Number, eval_expr(env, width)?);
+                    let height = check!(height, Number, eval_expr(env, height)?);
+                    let mode   = check_or!(mode, Sym, "repeat".to_string());
+                    let offset = check_or!(offset, Number, 0.0);
+
+                    if width < 0.0 || height < 0.0 {
+                        return err!("tile size cannot be negative");
+                    }
+                    if image.width == 0 || image.height == 0 {
+                        return err!("cannot tile an empty image");
+                    }
+                    let mirror = match mode.as_str() {
+                        "repeat" | "wrap" => false,
+                        "mirror" | "reflect" => true,
+                        _ => return err!("unknown tile mode: {}", mode),
                     };
-                    Ok(DataType::Number(result))
+
+                    let mut new_image = image.clone();
+                    new_image.tile(width as usize, height as usize, mirror, offset);
+                    Ok(DataType::Image(new_image))
                 }
 
-                _ => err!("unknown function: {}", f),
-            }
+                List::Sym("img-seamless") => {
+                    // (img-seamless image band), band is a fraction of the size
+                    let image = next_or!("missing image for `seamless`");
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let band  = check_or!(band, Number, 0.25);
+
+                    if !(0.0..=0.5).contains(&band) {
+                        return err!("seamless band must be between 0 and 0.5");
+                    }
+
+                    let mut new_image = image.clone();
+                    new_image.seamless(band);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("draw-rect") => {
+                    // (draw-rect image [x y w h] color radius)
+                    let image  = next_or!("missing image for `draw-rect`");
+                    let rect   = next_or!("missing rectangle for `draw-rect`");
+                    let color  = next_or!("missing colour for `draw-rect`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let rect   = eval_expr(env, rect)?;
+                    let color  = eval_expr(env, color)?;
+                    let radius = check_or!(radius, Number, 0.0);
+
+                    let [x, y, w, h] = match rect.as_numbers().as_deref() {
+                        Some(&[x, y, w, h]) => [x, y, w, h],
+                        _ => return err!("rectangle must be a vector of [x y w h]"),
+                    };
+                    let Some(color) = to_color(&color) else {
+                        return err!("color must be a colour, got {}", color.type_name());
+                    };
+
+                    let mut new_image = image.clone();
+                    let contour = raster::rounded_rect(x, y, w, h, radius);
+                    new_image.fill_contours(&[contour], color, FillRule::NonZero);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("draw-ellipse") => {
+                    // (draw-ellipse image [cx cy] r|[rx ry] color)
+                    let image  = next_or!("missing image for `draw-ellipse`");
+                    let center = next_or!("missing center for `draw-ellipse`");
+                    let radius = next_or!("missing radius for `draw-ellipse`");
+                    let color  = next_or!("missing colour for `draw-ellipse`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let center = eval_expr(env, center)?;
+                    let radius = eval_expr(env, radius)?;
+                    let color  = eval_expr(env, color)?;
+
+                    let Some(&[cx, cy]) = center.as_numbers().as_deref() else {
+                        return err!("center must be a vector of two numbers");
+                    };
+                    let (rx, ry) = match (&radius, radius.as_numbers().as_deref()) {
+                        (DataType::Number(r), _) => (*r, *r),
+                        (_, Some(&[rx, ry])) => (rx, ry),
+                        _ => return err!("radius must be a number or a vector of two numbers"),
+                    };
+                    let Some(color) = to_color(&color) else {
+                        return err!("color must be a colour, got {}", color.type_name());
+                    };
+
+                    let mut new_image = image.clone();
+                    let contour = raster::ellipse(cx, cy, rx.abs(), ry.abs());
+                    new_image.fill_contours(&[contour], color, FillRule::NonZero);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("draw-polygon") => {
+                    // (draw-polygon image [[x y] ...] color 'nonzero|'evenodd)
+                    let image  = next_or!("missing image for `draw-polygon`");
+                    let points = next_or!("missing points for `draw-polygon`");
+                    let color  = next_or!("missing colour for `draw-polygon`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let points = points!(points, eval_expr(env, points)?);
+                    let color  = eval_expr(env, color)?;
+                    let rule   = fill_rule!(rule);
+
+                    let Some(color) = to_color(&color) else {
+                        return err!("color must be a colour, got {}", color.type_name());
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.fill_contours(&[points], color, rule);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("draw-polyline")
+                | List::Sym("draw-bezier") => {
+                    // (draw-polyline image [[x y] ...] color width join cap)
+                    // (draw-bezier image [[x y] ...] color width 'cubic|'quadratic join cap)
+                    let image  = next_or!("missing image for line");
+                    let points = next_or!("missing points for line");
+                    let color  = next_or!("missing colour for line");
+                    let width  = next_or!("missing width for line");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let points = points!(points, eval_expr(env, points)?);
+                    let color  = eval_expr(env, color)?;
+                    let width  = check!(width, Number, eval_expr(env, width)?);
+
+                    let Some(color) = to_color(&color) else {
+                        return err!("color must be a colour, got {}", color.type_name());
+                    };
+
+                    let points = if let List::Sym("draw-bezier") = f {
+                        let degree = check_or!(degree, Sym, "cubic".to_string());
+                        let step = match degree.as_str() {
+                            "cubic" => 3,
+                            "quadratic" => 2,
+                            _ => return err!("unknown bezier degree: {}", degree),
+                        };
+                        if points.len() < step + 1 || (points.len() - 1) % step != 0 {
+                            return err!("a {} bezier needs 1 + {}n points, got {}", degree, step, points.len());
+                        }
+                        let mut flat = vec![points[0]];
+                        for c in points[1..].chunks(step) {
+                            let p0 = *flat.last().unwrap();
+                            match *c {
+                                [p1, p2] => raster::quadratic(&mut flat, p0, p1, p2),
+                                [p1, p2, p3] => raster::cubic(&mut flat, p0, p1, p2, p3),
+                                _ => unreachable!(),
+                            }
+                        }
+                        flat
+                    } else {
+                        points
+                    };
+                    let style = stroke_style!(width);
+
+                    let mut new_image = image.clone();
+                    let pieces = raster::stroke(&points, false, &style);
+                    new_image.fill_contours(&pieces, color, FillRule::NonZero);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("path") => Ok(DataType::Path(Path::new())),
+
+                List::Sym("path-svg") => {
+                    // (path-svg "M 0 0 L 10 10 Z")
+                    let data = next_or!("missing path data for `path-svg`");
+                    let data = check!(data, Str, eval_expr(env, data)?);
+                    match Path::from_svg(&data) {
+                        Ok(path) => Ok(DataType::Path(path)),
+                        Err(e) => err!("failed to parse path data: {}", e),
+                    }
+                }
+
+                List::Sym("move-to")
+                | List::Sym("line-to") => {
+                    let path = next_or!("missing path");
+                    let mut path = check!(path, Path, eval_expr(env, path)?);
+                    let p = point!(point, "missing point");
+                    if let List::Sym("move-to") = f {
+                        path.move_to(p);
+                    } else {
+                        path.line_to(p);
+                    }
+                    Ok(DataType::Path(path))
+                }
+
+                List::Sym("curve-to") => {
+                    // (curve-to path [c1] [c2] [p]), or (curve-to path [c] [p]) for a quadratic
+                    let path = next_or!("missing path for `curve-to`");
+                    let mut path = check!(path, Path, eval_expr(env, path)?);
+                    let c1 = point!(control, "missing control point for `curve-to`");
+                    let c2 = point!(point, "missing end point for `curve-to`");
+                    match iter.next() {
+                        Some(item) => {
+                            let end = eval_expr(env, item)?;
+                            let Some(&[x, y]) = end.as_numbers().as_deref() else {
+                                return err!("end must be a vector of two numbers, got {}", end.type_name());
+                            };
+                            path.cubic_to(c1, c2, [x, y]);
+                        }
+                        None => path.quad_to(c1, c2),
+                    }
+                    Ok(DataType::Path(path))
+                }
+
+                List::Sym("arc-to") => {
+                    // (arc-to path [rx ry] rotation large sweep [x y]), flags are 0 or 1 as in SVG
+                    let path     = next_or!("missing path for `arc-to`");
+                    let mut path = check!(path, Path, eval_expr(env, path)?);
+                    let radii    = point!(radii, "missing radii for `arc-to`");
+                    let rotation = next_or!("missing rotation for `arc-to`");
+                    let large    = next_or!("missing large arc flag for `arc-to`");
+                    let sweep    = next_or!("missing sweep flag for `arc-to`");
+                    let rotation = check!(rotation, Number, eval_expr(env, rotation)?);
+                    let large    = check!(large, Number, eval_expr(env, large)?);
+                    let sweep    = check!(sweep, Number, eval_expr(env, sweep)?);
+                    let end      = point!(end, "missing end point for `arc-to`");
+
+                    path.arc_to(radii, rotation, large != 0.0, sweep != 0.0, end);
+                    Ok(DataType::Path(path))
+                }
+
+                List::Sym("close") => {
+                    let path = next_or!("missing path for `close`");
+                    let mut path = check!(path, Path, eval_expr(env, path)?);
+                    path.close();
+                    Ok(DataType::Path(path))
+                }
+
+                List::Sym("path-transform")
+                | List::Sym("path-translate")
+                | List::Sym("path-scale")
+                | List::Sym("path-rotate") => {
+                    // (path-transform path [[a b c] [d e f]])
+                    // (path-translate path [dx dy])
+                    // (path-scale path s|[sx sy] [px py])
+                    // (path-rotate path degrees [px py]), clockwise
+                    let path  = next_or!("missing path for transform");
+                    let value = next_or!("missing amount for transform");
+
+                    let mut path = check!(path, Path, eval_expr(env, path)?);
+                    let value = eval_expr(env, value)?;
+                    let pivot = check_or!(pivot, Vec, vec![DataType::Number(0.0), DataType::Number(0.0)]);
+                    let Some(&[px, py]) = DataType::Vec(pivot).as_numbers().as_deref() else {
+                        return err!("pivot must be a vector of two numbers");
+                    };
+
+                    let matrix = match (f, &value, value.as_numbers().as_deref()) {
+                        (List::Sym("path-transform"), _, _) => match to_matrix::<6>(&value) {
+                            Some(matrix) => matrix,
+                            None => return err!("affine matrix must be [[a b c] [d e f]] or [a b c d e f]"),
+                        },
+                        (List::Sym("path-translate"), _, Some(&[dx, dy])) => [1.0, 0.0, dx, 0.0, 1.0, dy],
+                        (List::Sym("path-scale"), DataType::Number(s), _) => [*s, 0.0, px - s * px, 0.0, *s, py - s * py],
+                        (List::Sym("path-scale"), _, Some(&[sx, sy])) => [sx, 0.0, px - sx * px, 0.0, sy, py - sy * py],
+                        (List::Sym("path-rotate"), DataType::Number(degrees), _) => {
+                            let (sin, cos) = degrees.to_radians().sin_cos();
+                            [cos, -sin, px - cos * px + sin * py, sin, cos, py - sin * px - cos * py]
+                        }
+                        _ => return err!("invalid transform amount: {}", value.type_name()),
+                    };
+                    path.transform(&matrix);
+                    Ok(DataType::Path(path))
+                }
+
+                List::Sym("path-bbox") => {
+                    // (path-bbox path) => [x y w h], nil for an empty path
+                    let path = next_or!("missing path for `path-bbox`");
+                    let path = check!(path, Path, eval_expr(env, path)?);
+                    match path.bbox() {
+                        Some(bbox) => Ok(DataType::Vec(bbox.map(DataType::Number).to_vec())),
+                        None => Ok(DataType::Nil),
+                    }
+                }
+
+                List::Sym("path-fill") => {
+                    // (path-fill image path color 'nonzero|'evenodd)
+                    let image = next_or!("missing image for `path-fill`");
+                    let path  = next_or!("missing path for `path-fill`");
+                    let color = next_or!("missing colour for `path-fill`");
+
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let path  = check!(path, Path, eval_expr(env, path)?);
+                    let color = eval_expr(env, color)?;
+                    let rule  = fill_rule!(rule);
+
+                    let Some(color) = to_color(&color) else {
+                        return err!("color must be a colour, got {}", color.type_name());
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.fill_path(&path, color, rule);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("path-stroke") => {
+                    // (path-stroke image path color width join cap)
+                    let image = next_or!("missing image for `path-stroke`");
+                    let path  = next_or!("missing path for `path-stroke`");
+                    let color = next_or!("missing colour for `path-stroke`");
+                    let width = next_or!("missing width for `path-stroke`");
+
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let path  = check!(path, Path, eval_expr(env, path)?);
+                    let color = eval_expr(env, color)?;
+                    let width = check!(width, Number, eval_expr(env, width)?);
+                    let style = stroke_style!(width);
+
+                    let Some(color) = to_color(&color) else {
+                        return err!("color must be a colour, got {}", color.type_name());
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.stroke_path(&path, color, &style);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("path-clip") => {
+                    // (path-clip image path 'nonzero|'evenodd)
+                    let image = next_or!("missing image for `path-clip`");
+                    let path  = next_or!("missing path for `path-clip`");
+
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let path  = check!(path, Path, eval_expr(env, path)?);
+                    let rule  = fill_rule!(rule);
+
+                    let mut new_image = image.clone();
+                    new_image.clip_path(&path, rule);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("text") => {
+                    // (text "font.ttf" "string" size color wrap-width 'left|'center|'right spacing line-height)
+                    // a wrap width of 0 only breaks lines on newlines
+                    let font  = next_or!("missing font path for `text`");
+                    let text  = next_or!("missing string for `text`");
+                    let size  = next_or!("missing size for `text`");
+
+                    let font  = check!(font, Str, eval_expr(env, font)?);
+                    let text  = check!(text, Str, eval_expr(env, text)?);
+                    let size  = check!(size, Number, eval_expr(env, size)?);
+                    let color = color_or!(color, Rgba([0, 0, 0, 255]));
+                    let wrap  = check_or!(wrap, Number, 0.0);
+                    let align = check_or!(align, Sym, "left".to_string());
+                    let letter_spacing = check_or!(spacing, Number, 0.0);
+                    let line_height    = check_or!(line_height, Number, 1.0);
+
+                    if size <= 0.0 {
+                        return err!("text size must be positive");
+                    }
+                    if wrap < 0.0 {
+                        return err!("wrap width cannot be negative");
+                    }
+                    let align = match align.as_str() {
+                        "left" | "start" => Align::Left,
+                        "center" | "centre" | "middle" => Align::Center,
+                        "right" | "end" => Align::Right,
+                        _ => return err!("unknown text alignment: {}", align),
+                    };
+
+                    let style = TextStyle {
+                        size,
+                        color,
+                        letter_spacing,
+                        line_height,
+                        align,
+                        wrap: if wrap > 0.0 { Some(wrap) } else { None },
+                    };
+                    match Image::from_text(&font, &text, &style) {
+                        Ok(image) => Ok(DataType::Image(image)),
+                        Err(e) => err!("failed to render text: {}", e),
+                    }
+                }
+
+                List::Sym("img-mix") => {
+                    // first = bottom, second = top
+                    let image_a = next_or!("missing first image for `mix`");
+                    let image_b = next_or!("missing second image for `mix`");
+                    let mode    = next_or!("missing blend mode for `mix`");
+
+                    let image_a = check!(image_a, Image, eval_expr(env, image_a)?);
+                    let image_b = check!(image_b, Image, eval_expr(env, image_b)?);
+                    let mode    = check!(mode, Sym, eval_expr(env, mode)?);
+
+                    let blend_mode = match mode.as_str() {
+                        "normal"   => BlendMode::Normal,
+                        "multiply" => BlendMode::Multiply,
+                        "screen"   => BlendMode::Screen,
+                        "overlay"  => BlendMode::Overlay,
+                        "add"      => BlendMode::Add,
+                        _ => return err!("unknown blend mode: {}", mode),
+                    };
+
+                    // (img-mix bg fg mode [mask] ['luma|'alpha])
+                    let mask = match iter.next() {
+                        Some(item) => {
+                            let mask = check!(mask, Image, eval_expr(env, item)?);
+                            let source = mask_source!(source);
+                            Some(mask.mask_plane(image_b.width, image_b.height, source))
+                        }
+                        None => None,
+                    };
+
+                    let mut new_image = image_a.clone();
+                    new_image.blend_images(&image_b, blend_mode, mask.as_ref());
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-mask") => {
+                    // (img-mask image mask ['luma|'alpha] ['keep|'invert])
+                    let image = next_or!("missing image for `img-mask`");
+                    let mask  = next_or!("missing mask for `img-mask`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let mask   = check!(mask, Image, eval_expr(env, mask)?);
+                    let source = mask_source!(source);
+                    let invert = check_or!(invert, Sym, "keep".to_string());
+
+                    let mut plane = mask.mask_plane(image.width, image.height, source);
+                    match invert.as_str() {
+                        "keep" | "normal" => {}
+                        "invert" | "inverse" => plane.mapv_inplace(|m| 255.0 - m),
+                        _ => return err!("unknown mask mode: {}", invert),
+                    }
+
+                    let mut new_image = image.clone();
+                    new_image.apply_mask(&plane);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-alpha") => {
+                    let image = next_or!("missing image for `img-alpha`");
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    Ok(DataType::Image(Image::from_grey(&image.alpha_plane())))
+                }
+
+                List::Sym("img-set-alpha") => {
+                    // (img-set-alpha image mask|alpha ['luma|'alpha])
+                    let image = next_or!("missing image for `img-set-alpha`");
+                    let alpha = next_or!("missing alpha for `img-set-alpha`");
+
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let alpha = eval_expr(env, alpha)?;
+
+                    let plane = match alpha {
+                        DataType::Number(a) => Array2::from_elem((image.height, image.width), a as f32),
+                        DataType::Image(mask) => {
+                            let source = mask_source!(source);
+                            mask.mask_plane(image.width, image.height, source)
+                        }
+                        _ => return err!("alpha must be a number or an image, got {}", alpha.type_name()),
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.set_alpha(&plane);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-invert-alpha") => {
+                    let image = next_or!("missing image for `img-invert-alpha`");
+                    let image = check!(image, Image, eval_expr(env, image)?);
+
+                    let mut new_image = image.clone();
+                    new_image.invert_alpha();
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-feather") => {
+                    let image  = next_or!("missing image for `img-feather`");
+                    let radius = next_or!("missing radius for `img-feather`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let radius = check!(radius, Number, eval_expr(env, radius)?);
+
+                    if radius < 0.0 {
+                        return err!("radius must not be negative");
+                    }
+
+                    let mut new_image = image.clone();
+                    new_image.feather(radius.round() as usize);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-premultiply") => {
+                    let image = next_or!("missing image for `img-premultiply`");
+                    let image = check!(image, Image, eval_expr(env, image)?);
+
+                    let mut new_image = image.clone();
+                    new_image.premultiply_alpha();
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-unpremultiply") => {
+                    let image = next_or!("missing image for `img-unpremultiply`");
+                    let image = check!(image, Image, eval_expr(env, image)?);
+
+                    let mut new_image = image.clone();
+                    new_image.unpremultiply_alpha();
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-map") => {
+                    // (img-map image [r g b a]), expressions over r g b a x y u v width height
+                    let image = next_or!("missing image for `img-map`");
+                    let expr  = next_or!("missing expression for `img-map`");
+
+                    let image    = check!(image, Image, eval_expr(env, image)?);
+                    let channels = compile_pixel(env, &expr, &|name| pixel_slot(name, 1))?;
+
+                    let (width, height) = (image.width, image.height);
+                    let mut new_image = image.clone();
+                    new_image.map_pixels(|x, y, pixel| {
+                        let mut slots = [0.0; 10];
+                        pixel_coords(&mut slots, x, y, width, height);
+                        for c in 0..4 {
+                            slots[6 + c] = pixel[c] as f64;
+                        }
+                        eval_pixel(&channels, &slots, pixel[3])
+                    });
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-map2")
+                | List::Sym("img-combine") => {
+                    // (img-map2 a b [r g b a]), (img-combine [images ...] [r g b a])
+                    let images = if let List::Sym("img-map2") = f {
+                        let a = next_or!("missing first image for `img-map2`");
+                        let b = next_or!("missing second image for `img-map2`");
+                        vec![
+                            check!(a, Image, eval_expr(env, a)?),
+                            check!(b, Image, eval_expr(env, b)?),
+                        ]
+                    } else {
+                        let images = next_or!("missing images for `img-combine`");
+                        let images = eval_expr(env, images)?;
+                        match images {
+                            DataType::Vec(items) => items
+                                .into_iter()
+                                .map(|item| match item {
+                                    DataType::Image(img) => Ok(img),
+                                    other => err!("images must only contain images, got {}", other.type_name()),
+                                })
+                                .collect::<Result<Vec<_>, _>>()?,
+                            _ => return err!("images must be a vector of images, got {}", images.type_name()),
+                        }
+                    };
+                    let Some(expr) = iter.next() else {
+                        return err!("missing expression for `{}`", f);
+                    };
+
+                    if images.is_empty() || images.len() > MAX_PIXEL_IMAGES {
+                        return err!("expected 1 to {} images, got {}", MAX_PIXEL_IMAGES, images.len());
+                    }
+                    let (width, height) = (images[0].width, images[0].height);
+                    if images.iter().any(|img| (img.width, img.height) != (width, height)) {
+                        return err!("images must all be the same size");
+                    }
+
+                    let count = images.len();
+                    let channels = compile_pixel(env, &expr, &|name| pixel_slot(name, count))?;
+
+                    let mut new_image = images[0].clone();
+                    new_image.map_pixels(|x, y, pixel| {
+                        let mut slots = [0.0; 6 + 4 * MAX_PIXEL_IMAGES];
+                        pixel_coords(&mut slots, x, y, width, height);
+                        for (i, img) in images.iter().enumerate() {
+                            let other = img.image[[y, x]];
+                            for c in 0..4 {
+                                slots[6 + i * 4 + c] = other[c] as f64;
+                            }
+                        }
+                        eval_pixel(&channels, &slots, pixel[3])
+                    });
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-channel") => {
+                    // (img-channel image 'r|'g|'b|'a|'luma)
+                    let image   = next_or!("missing image for `img-channel`");
+                    let channel = next_or!("missing channel for `img-channel`");
+
+                    let image   = check!(image, Image, eval_expr(env, image)?);
+                    let channel = check!(channel, Sym, eval_expr(env, channel)?);
+
+                    let channel = match channel.as_str() {
+                        "r" | "red" => Channel::Red,
+                        "g" | "green" => Channel::Green,
+                        "b" | "blue" => Channel::Blue,
+                        "a" | "alpha" => Channel::Alpha,
+                        "luma" | "luminance" | "l" => Channel::Luma,
+                        _ => return err!("unknown channel: {}", channel),
+                    };
+                    Ok(DataType::Image(Image::from_grey(&image.channel(channel))))
+                }
+
+                List::Sym("img-merge") => {
+                    // (img-merge r g b [a]), each a greyscale image or a number
+                    let mut sources = Vec::with_capacity(4);
+                    for item in iter.by_ref().take(4) {
+                        sources.push(eval_expr(env, item)?);
+                    }
+                    if iter.next().is_some() {
+                        return err!("`img-merge` takes at most four channels");
+                    }
+                    if sources.len() < 3 {
+                        return err!("`img-merge` needs at least r, g and b channels");
+                    }
+
+                    let Some((width, height)) = sources.iter().find_map(|s| match s {
+                        DataType::Image(img) => Some((img.width, img.height)),
+                        _ => None,
+                    }) else {
+                        return err!("`img-merge` needs at least one channel image");
+                    };
+
+                    let mut planes = Vec::with_capacity(4);
+                    for source in &sources {
+                        planes.push(match source {
+                            DataType::Image(img) if (img.width, img.height) == (width, height) =>
+                                img.channel(Channel::Luma),
+                            DataType::Image(_) => return err!("channel images must all be the same size"),
+                            DataType::Number(n) => Array2::from_elem((height, width), *n as f32),
+                            _ => return err!("channel must be an image or a number, got {}", source.type_name()),
+                        });
+                    }
+                    if planes.len() == 3 {
+                        planes.push(Array2::from_elem((height, width), 255.0));
+                    }
+
+                    let mut new_image = Image::new(width, height);
+                    new_image.set_channels(&planes.try_into().expect("Expected 4 channels"));
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-swizzle") => {
+                    // (img-swizzle image 'bgra), also "0" and "1" for empty and full
+                    let image   = next_or!("missing image for `img-swizzle`");
+                    let pattern = next_or!("missing pattern for `img-swizzle`");
+
+                    let image   = check!(image, Image, eval_expr(env, image)?);
+                    let pattern = match eval_expr(env, pattern)? {
+                        DataType::Sym(s) | DataType::Str(s) => s,
+                        other => return err!("pattern must be a symbol or string, got {}", other.type_name()),
+                    };
+                    let Some(pattern) = Swizzle::parse(&pattern) else {
+                        return err!("pattern must be 3 or 4 of r, g, b, a, 0 and 1, got {}", pattern);
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.swizzle(pattern);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-channel-mixer") => {
+                    // (eff-channel-mixer image [[rr rg rb ra ro] [gr ..] [br ..] [ar ..]])
+                    let image  = next_or!("missing image for `eff-channel-mixer`");
+                    let matrix = next_or!("missing matrix for `eff-channel-mixer`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let matrix = eval_expr(env, matrix)?;
+                    let Some(matrix) = to_matrix::<20>(&matrix) else {
+                        return err!("matrix must be 4 rows of 5 numbers, got {}", matrix.type_name());
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.channel_mixer(&matrix);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-blur") => {
+                    let image  = next_or!("missing image for `blur`");
+                    let radius = next_or!("missing radius for `blur`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let radius = check!(radius, Number, eval_expr(env, radius)?);
+                    let edge   = edge_mode!(edge, "clamp");
+
+                    if radius < 0.0 {
+                        return err!("blur radius cannot be negative");
+                    }
+
+                    let mut new_image = image.clone();
+                    new_image.blur(radius as usize, edge);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-convolve") => {
+                    // (eff-convolve image [[..] [..]] norm edge)
+                    // (eff-convolve image [h..] [v..] norm edge)
+                    let image  = next_or!("missing image for `convolve`");
+                    let kernel = next_or!("missing kernel for `convolve`");
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let kernel = check!(kernel, Vec, eval_expr(env, kernel)?);
+
+                    let is_separable = kernel.iter().all(|k| matches!(k, DataType::Number(_)));
+                    let vertical = if is_separable {
+                        let vertical = next_or!("missing vertical kernel for separable `convolve`");
+                        Some(check!(vertical, Vec, eval_expr(env, vertical)?))
+                    } else {
+                        None
+                    };
+
+                    let norm = check_or!(norm, Sym, "sum".to_string());
+                    let norm = match norm.as_str() {
+                        "none" | "raw" => Normalize::None,
+                        "sum" | "normalize" => Normalize::Sum,
+                        "abs" => Normalize::Abs,
+                        _ => return err!("unknown kernel normalisation: {}", norm),
+                    };
+                    let edge = edge_mode!(edge, "clamp");
+
+                    let mut new_image = image.clone();
+                    if let Some(vertical) = vertical {
+                        let (h, v) = match (DataType::Vec(kernel).as_numbers(), DataType::Vec(vertical).as_numbers()) {
+                            (Some(h), Some(v)) if !h.is_empty() && !v.is_empty() => (h, v),
+                            _ => return err!("separable kernels must be non-empty vectors of numbers"),
+                        };
+                        let h = h.into_iter().map(|w| w as f32).collect::<Vec<_>>();
+                        let v = v.into_iter().map(|w| w as f32).collect::<Vec<_>>();
+                        new_image.convolve_separable(&h, &v, norm, edge);
+                    } else {
+                        let rows = match kernel.iter().map(|r| r.as_numbers()).collect::<Option<Vec<_>>>() {
+                            Some(rows) => rows,
+                            None => return err!("kernel rows must be vectors of numbers"),
+                        };
+                        let width = rows[0].len();
+                        if width == 0 || rows.iter().any(|r| r.len() != width) {
+                            return err!("kernel rows must all have the same non-zero length");
+                        }
+                        let kernel = Array2::from_shape_fn((rows.len(), width), |(y, x)| rows[y][x] as f32);
+                        new_image.convolve(&kernel, norm, edge);
+                    }
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("lut-load") => {
+                    let path = next_or!("missing path for `lut-load`");
+                    let path = check!(path, Str, eval_expr(env, path)?);
+                    match Lut::from_file(&path) {
+                        Ok(lut) => Ok(DataType::Lut(lut)),
+                        Err(e) => err!("failed to load LUT: {}", e),
+                    }
+                }
+
+                List::Sym("lut-identity") => {
+                    let size = next_or!("missing size for `lut-identity`");
+                    let size = check!(size, Number, eval_expr(env, size)?);
+                    if !(2.0..=256.0).contains(&size) {
+                        return err!("LUT size must be between 2 and 256");
+                    }
+                    Ok(DataType::Image(Image::lut_identity(size as usize)))
+                }
+
+                List::Sym("lut-save") => {
+                    // image must be a (transformed) `lut-identity` lattice
+                    let image = next_or!("missing image for `lut-save`");
+                    let path  = next_or!("missing path for `lut-save`");
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let path  = check!(path, Str, eval_expr(env, path)?);
+                    let title = check_or!(title, Str, "raspare".to_string());
+
+                    let mut lut = match Lut::from_identity_image(&image) {
+                        Ok(lut) => lut,
+                        Err(e) => return err!("invalid LUT lattice: {}", e),
+                    };
+                    lut.title = Some(title);
+                    match lut.to_file(&path) {
+                        Ok(()) => Ok(DataType::Nil),
+                        Err(e) => err!("failed to save LUT: {}", e),
+                    }
+                }
+
+                List::Sym("eff-lut") => {
+                    let image  = next_or!("missing image for `lut`");
+                    let lut    = next_or!("missing LUT for `lut`");
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let lut = match eval_expr(env, lut)? {
+                        DataType::Lut(lut) => lut,
+                        DataType::Str(path) => match Lut::from_file(&path) {
+                            Ok(lut) => lut,
+                            Err(e) => return err!("failed to load LUT: {}", e),
+                        },
+                        other => return err!(
+                            "lut must be of type Lut or Str, got {}", other.type_name()),
+                    };
+                    let method = check_or!(method, Sym, "tetrahedral".to_string());
+
+                    let interp = match method.as_str() {
+                        "tetrahedral" | "tetra" => LutInterp::Tetrahedral,
+                        "trilinear"   | "tri"   => LutInterp::Trilinear,
+                        _ => return err!("unknown LUT interpolation: {}", method),
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.apply_lut(&lut, interp);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-motion-blur") => {
+                    let image  = next_or!("missing image for `motion-blur`");
+                    let angle  = next_or!("missing angle for `motion-blur`");
+                    let length = next_or!("missing length for `motion-blur`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let angle  = check!(angle, Number, eval_expr(env, angle)?);
+                    let length = check!(length, Number, eval_expr(env, length)?);
+                    let edge   = edge_mode!(edge, "clamp");
+
+                    if length < 0.0 {
+                        return err!("blur length cannot be negative");
+                    }
+
+                    let mut new_image = image.clone();
+                    new_image.motion_blur(angle, length, edge);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-radial-blur")
+                | List::Sym("eff-zoom-blur") => {
+                    // (eff-radial-blur image angle [cx cy] edge)
+                    // (eff-zoom-blur image strength [cx cy] edge)
+                    let image  = next_or!("missing image for blur");
+                    let amount = next_or!("missing amount for blur");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let amount = check!(amount, Number, eval_expr(env, amount)?);
+                    let center = check_or!(center, Vec, vec![
+                        DataType::Number(image.width as f64 / 2.0),
+                        DataType::Number(image.height as f64 / 2.0),
+                    ]);
+                    let edge   = edge_mode!(edge, "clamp");
+
+                    let (cx, cy) = match DataType::Vec(center).as_numbers().as_deref() {
+                        Some(&[cx, cy]) => (cx, cy),
+                        _ => return err!("center must be a vector of two numbers"),
+                    };
+
+                    let mut new_image = image.clone();
+                    if let List::Sym("eff-radial-blur") = f {
+                        new_image.radial_blur(amount, cx, cy, edge);
+                    } else {
+                        new_image.zoom_blur(amount, cx, cy, edge);
+                    }
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-twirl")
+                | List::Sym("eff-spherize") => {
+                    // (eff-twirl image degrees radius [cx cy] edge filter)
+                    // (eff-spherize image amount radius [cx cy] edge filter)
+                    let image  = next_or!("missing image for distortion");
+                    let amount = next_or!("missing amount for distortion");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let amount = check!(amount, Number, eval_expr(env, amount)?);
+                    let radius = check_or!(radius, Number, image.width.min(image.height) as f64 / 2.0);
+                    let center = check_or!(center, Vec, vec![
+                        DataType::Number(image.width as f64 / 2.0),
+                        DataType::Number(image.height as f64 / 2.0),
+                    ]);
+                    let edge   = edge_mode!(edge, "clamp");
+                    let filter = filter!(filter, "bilinear");
+
+                    if radius <= 0.0 {
+                        return err!("distortion radius must be positive");
+                    }
+                    let (cx, cy) = match DataType::Vec(center).as_numbers().as_deref() {
+                        Some(&[cx, cy]) => (cx, cy),
+                        _ => return err!("center must be a vector of two numbers"),
+                    };
+
+                    let mut new_image = image.clone();
+                    if let List::Sym("eff-twirl") = f {
+                        new_image.twirl(amount, radius, cx, cy, filter, edge);
+                    } else {
+                        new_image.spherize(amount, radius, cx, cy, filter, edge);
+                    }
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-kaleidoscope") => {
+                    // (eff-kaleidoscope image segments degrees [cx cy] edge filter)
+                    let image    = next_or!("missing image for `kaleidoscope`");
+                    let segments = next_or!("missing segment count for `kaleidoscope`");
+
+                    let image    = check!(image, Image, eval_expr(env, image)?);
+                    let segments = check!(segments, Number, eval_expr(env, segments)?);
+                    let degrees  = check_or!(degrees, Number, 0.0);
+                    let center   = check_or!(center, Vec, vec![
+                        DataType::Number(image.width as f64 / 2.0),
+                        DataType::Number(image.height as f64 / 2.0),
+                    ]);
+                    let edge     = edge_mode!(edge, "mirror");
+                    let filter   = filter!(filter, "bilinear");
+
+                    if segments < 1.0 {
+                        return err!("kaleidoscope needs at least one segment");
+                    }
+                    let (cx, cy) = match DataType::Vec(center).as_numbers().as_deref() {
+                        Some(&[cx, cy]) => (cx, cy),
+                        _ => return err!("center must be a vector of two numbers"),
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.kaleidoscope(segments as usize, degrees, cx, cy, filter, edge);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-ripple") => {
+                    // (eff-ripple image amplitude wavelength 'both|'horizontal|'vertical|'radial edge filter)
+                    let image      = next_or!("missing image for `ripple`");
+                    let amplitude  = next_or!("missing amplitude for `ripple`");
+                    let wavelength = next_or!("missing wavelength for `ripple`");
+
+                    let image      = check!(image, Image, eval_expr(env, image)?);
+                    let amplitude  = check!(amplitude, Number, eval_expr(env, amplitude)?);
+                    let wavelength = check!(wavelength, Number, eval_expr(env, wavelength)?);
+                    let mode       = check_or!(mode, Sym, "both".to_string());
+                    let edge       = edge_mode!(edge, "clamp");
+                    let filter     = filter!(filter, "bilinear");
+
+                    if wavelength <= 0.0 {
+                        return err!("ripple wavelength must be positive");
+                    }
+                    let mode = match mode.as_str() {
+                        "horizontal" | "h" | "x" => Ripple::Horizontal,
+                        "vertical" | "v" | "y" => Ripple::Vertical,
+                        "both" | "hv" | "xy" => Ripple::Both,
+                        "radial" | "circular" => Ripple::Radial,
+                        _ => return err!("unknown ripple mode: {}", mode),
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.ripple(amplitude, wavelength, mode, filter, edge);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-polar") => {
+                    let image = next_or!("missing image for `polar`");
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let edge   = edge_mode!(edge, "wrap");
+                    let filter = filter!(filter, "bilinear");
+
+                    let mut new_image = image.clone();
+                    new_image.polar(filter, edge);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-unpolar") => {
+                    let image = next_or!("missing image for `unpolar`");
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let edge   = edge_mode!(edge, "clamp");
+                    let filter = filter!(filter, "bilinear");
+
+                    let mut new_image = image.clone();
+                    new_image.unpolar(filter, edge);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-displace") => {
+                    // (eff-displace image map amount|[ax ay] edge filter)
+                    // red moves along x, green along y, mid grey stays in place
+                    let image  = next_or!("missing image for `displace`");
+                    let map    = next_or!("missing displacement map for `displace`");
+                    let amount = next_or!("missing amount for `displace`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let map    = check!(map, Image, eval_expr(env, map)?);
+                    let amount = eval_expr(env, amount)?;
+                    let amount = match (&amount, amount.as_numbers().as_deref()) {
+                        (DataType::Number(a), _) => (*a, *a),
+                        (_, Some(&[ax, ay])) => (ax, ay),
+                        _ => return err!("displacement amount must be a number or a vector of two numbers"),
+                    };
+                    let edge   = edge_mode!(edge, "clamp");
+                    let filter = filter!(filter, "bilinear");
+
+                    let mut new_image = image.clone();
+                    new_image.displace(&map, amount, filter, edge);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-unsharp") => {
+                    let image     = next_or!("missing image for `unsharp`");
+                    let radius    = next_or!("missing radius for `unsharp`");
+                    let amount    = next_or!("missing amount for `unsharp`");
+
+                    let image     = check!(image, Image, eval_expr(env, image)?);
+                    let radius    = check!(radius, Number, eval_expr(env, radius)?);
+                    let amount    = check!(amount, Number, eval_expr(env, amount)?);
+                    let threshold = check_or!(threshold, Number, 0.0);
+                    let edge      = edge_mode!(edge, "clamp");
+
+                    if radius < 0.0 {
+                        return err!("unsharp radius cannot be negative");
+                    }
+
+                    let mut new_image = image.clone();
+                    new_image.unsharp_mask(radius as usize, amount as f32, threshold as f32, edge);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-high-pass") => {
+                    let image  = next_or!("missing image for `high-pass`");
+                    let radius = next_or!("missing radius for `high-pass`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let radius = check!(radius, Number, eval_expr(env, radius)?);
+                    let edge   = edge_mode!(edge, "clamp");
+
+                    if radius < 0.0 {
+                        return err!("high-pass radius cannot be negative");
+                    }
+
+                    let mut new_image = image.clone();
+                    new_image.high_pass(radius as usize, edge);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-sobel")
+                | List::Sym("eff-scharr") => {
+                    let image  = next_or!("missing image for gradient");
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let output = check_or!(output, Sym, "magnitude".to_string());
+                    let edge   = edge_mode!(edge, "clamp");
+
+                    let op = if let List::Sym("eff-sobel") = f {
+                        GradientOp::Sobel
+                    } else {
+                        GradientOp::Scharr
+                    };
+                    let output = match output.as_str() {
+                        "magnitude" | "mag" => GradientOutput::Magnitude,
+                        "direction" | "dir" => GradientOutput::Direction,
+                        "x" => GradientOutput::X,
+                        "y" => GradientOutput::Y,
+                        _ => return err!("unknown gradient output: {}", output),
+                    };
+
+                    Ok(DataType::Image(image.gradient(op, output, edge)))
+                }
+
+                List::Sym("eff-laplacian") => {
+                    let image = next_or!("missing image for `laplacian`");
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let sigma = check_or!(sigma, Number, 0.0);
+                    let mode  = check_or!(mode, Sym, "abs".to_string());
+                    let edge  = edge_mode!(edge, "clamp");
+
+                    if sigma < 0.0 {
+                        return err!("laplacian sigma cannot be negative");
+                    }
+                    let signed = match mode.as_str() {
+                        "abs" => false,
+                        "signed" => true,
+                        _ => return err!("unknown laplacian mode: {}", mode),
+                    };
+
+                    Ok(DataType::Image(image.laplacian(sigma as f32, signed, edge)))
+                }
+
+                List::Sym("eff-canny") => {
+                    let image = next_or!("missing image for `canny`");
+                    let sigma = next_or!("missing sigma for `canny`");
+                    let low   = next_or!("missing low threshold for `canny`");
+                    let high  = next_or!("missing high threshold for `canny`");
+
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let sigma = check!(sigma, Number, eval_expr(env, sigma)?);
+                    let low   = check!(low, Number, eval_expr(env, low)?);
+                    let high  = check!(high, Number, eval_expr(env, high)?);
+                    let edge  = edge_mode!(edge, "clamp");
+
+                    if sigma < 0.0 {
+                        return err!("canny sigma cannot be negative");
+                    }
+                    if low > high {
+                        return err!("canny low threshold must not exceed the high threshold");
+                    }
+
+                    Ok(DataType::Image(image.canny(sigma as f32, low as f32, high as f32, edge)))
+                }
+
+                List::Sym("eff-dilate")
+                | List::Sym("eff-erode")
+                | List::Sym("eff-open")
+                | List::Sym("eff-close") => {
+                    // (eff-dilate image radius shape target)
+                    // (eff-dilate image [rx ry] target)
+                    // (eff-dilate image [[0 1 0] [1 1 1] [0 1 0]] target)
+                    let image   = next_or!("missing image for morphology");
+                    let element = next_or!("missing structuring element for morphology");
+                    let image   = check!(image, Image, eval_expr(env, image)?);
+
+                    let element = match eval_expr(env, element)? {
+                        DataType::Number(r) if r >= 0.0 => {
+                            let shape = check_or!(shape, Sym, "square".to_string());
+                            match shape.as_str() {
+                                "square" | "rect" => Element::Rect(r as usize, r as usize),
+                                "disk" | "circle" => Element::Disk(r as usize),
+                                _ => return err!("unknown structuring element shape: {}", shape),
+                            }
+                        }
+                        v @ DataType::Vec(_) => match v.as_numbers().as_deref() {
+                            Some(&[rx, ry]) if rx >= 0.0 && ry >= 0.0 => Element::Rect(rx as usize, ry as usize),
+                            Some(_) => return err!("rectangle radii must be two non-negative numbers"),
+                            None => {
+                                let DataType::Vec(rows) = v else { unreachable!() };
+                                let rows = match rows.iter().map(|r| r.as_numbers()).collect::<Option<Vec<_>>>() {
+                                    Some(rows) => rows,
+                                    None => return err!("structuring element rows must be vectors of numbers"),
+                                };
+                                let width = rows.first().map_or(0, |r| r.len());
+                                if width == 0 || rows.iter().any(|r| r.len() != width) {
+                                    return err!("structuring element rows must all have the same non-zero length");
+                                }
+                                Element::Custom(Array2::from_shape_fn((rows.len(), width), |(y, x)| rows[y][x] != 0.0))
+                            }
+                        },
+                        other => return err!(
+                            "structuring element must be a non-negative number or a vector, got {}",
+                            other.type_name()),
+                    };
+
+                    let target = check_or!(target, Sym, "rgba".to_string());
+                    let target = match target.as_str() {
+                        "alpha" | "a" => MorphTarget::Alpha,
+                        "rgba" | "channels" => MorphTarget::Channels,
+                        _ => return err!("unknown morphology target: {}", target),
+                    };
+
+                    let op = match f {
+                        List::Sym("eff-dilate") => MorphOp::Dilate,
+                        List::Sym("eff-erode")  => MorphOp::Erode,
+                        List::Sym("eff-open")   => MorphOp::Open,
+                        _                       => MorphOp::Close,
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.morph(op, &element, target);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-median") => {
+                    let image  = next_or!("missing image for `median`");
+                    let radius = next_or!("missing radius for `median`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let radius = check!(radius, Number, eval_expr(env, radius)?);
+
+                    if radius < 0.0 {
+                        return err!("median radius cannot be negative");
+                    }
+
+                    let mut new_image = image.clone();
+                    new_image.median(radius as usize);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-bilateral") => {
+                    let image         = next_or!("missing image for `bilateral`");
+                    let sigma_spatial = next_or!("missing spatial sigma for `bilateral`");
+                    let sigma_range   = next_or!("missing range sigma for `bilateral`");
+
+                    let image         = check!(image, Image, eval_expr(env, image)?);
+                    let sigma_spatial = check!(sigma_spatial, Number, eval_expr(env, sigma_spatial)?);
+                    let sigma_range   = check!(sigma_range, Number, eval_expr(env, sigma_range)?);
+
+                    if sigma_spatial < 0.0 || sigma_range < 0.0 {
+                        return err!("bilateral sigmas cannot be negative");
+                    }
+
+                    let mut new_image = image.clone();
+                    new_image.bilateral(sigma_spatial as f32, sigma_range as f32);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-kuwahara") => {
+                    let image  = next_or!("missing image for `kuwahara`");
+                    let radius = next_or!("missing radius for `kuwahara`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let radius = check!(radius, Number, eval_expr(env, radius)?);
+                    let kind   = check_or!(kind, Sym, "classic".to_string());
+
+                    if radius < 0.0 {
+                        return err!("kuwahara radius cannot be negative");
+                    }
+                    let kind = match kind.as_str() {
+                        "classic" => Kuwahara::Classic,
+                        "anisotropic" | "aniso" => Kuwahara::Anisotropic,
+                        _ => return err!("unknown kuwahara variant: {}", kind),
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.kuwahara(radius as usize, kind);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-glow")
+                | List::Sym("eff-bloom") => {
+                    let image     = next_or!("missing image for glow");
+                    let threshold = next_or!("missing threshold for glow");
+                    let radius    = next_or!("missing radius for glow");
+                    let intensity = next_or!("missing intensity for glow");
+
+                    let image     = check!(image, Image, eval_expr(env, image)?);
+                    let threshold = check!(threshold, Number, eval_expr(env, threshold)?);
+                    let radius    = check!(radius, Number, eval_expr(env, radius)?);
+                    let intensity = check!(intensity, Number, eval_expr(env, intensity)?);
+                    let tint      = color_or!(tint, Rgba([255, 255, 255, 255]));
+
+                    if radius < 0.0 || intensity < 0.0 {
+                        return err!("glow radius and intensity cannot be negative");
+                    }
+
+                    let (blend, levels) = if let List::Sym("eff-glow") = f {
+                        (BlendMode::Screen, 1)
+                    } else {
+                        (BlendMode::Add, 3)
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.glow(&Glow {
+                        threshold: threshold as f32,
+                        radius: radius as usize,
+                        intensity: intensity as f32,
+                        tint,
+                        blend,
+                        levels,
+                    });
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-drop-shadow") => {
+                    let image   = next_or!("missing image for `drop-shadow`");
+                    let dx      = next_or!("missing x offset for `drop-shadow`");
+                    let dy      = next_or!("missing y offset for `drop-shadow`");
+                    let radius  = next_or!("missing blur radius for `drop-shadow`");
+
+                    let image   = check!(image, Image, eval_expr(env, image)?);
+                    let dx      = check!(dx, Number, eval_expr(env, dx)?);
+                    let dy      = check!(dy, Number, eval_expr(env, dy)?);
+                    let radius  = check!(radius, Number, eval_expr(env, radius)?);
+                    let color   = color_or!(color, Rgba([0, 0, 0, 255]));
+                    let opacity = check_or!(opacity, Number, 0.75);
+                    let spread  = check_or!(spread, Number, 0.0);
+
+                    if radius < 0.0 || spread < 0.0 {
+                        return err!("shadow radius and spread cannot be negative");
+                    }
+
+                    let mut new_image = image.clone();
+                    new_image.drop_shadow(&DropShadow {
+                        dx: dx.round() as isize,
+                        dy: dy.round() as isize,
+                        radius: radius as usize,
+                        color,
+                        opacity: opacity as f32,
+                        spread: spread as usize,
+                    });
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-pixelate") => {
+                    let image = next_or!("missing image for `pixelate`");
+                    let block = next_or!("missing block size for `pixelate`");
+
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let block = check!(block, Number, eval_expr(env, block)?);
+                    let mode  = check_or!(mode, Sym, "average".to_string());
+
+                    if block < 1.0 {
+                        return err!("pixelate block size must be at least 1");
+                    }
+                    let mode = match mode.as_str() {
+                        "average" | "avg" => Pixelate::Average,
+                        "sample" => Pixelate::Sample,
+                        _ => return err!("unknown pixelate mode: {}", mode),
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.pixelate(block as usize, mode);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-posterize") => {
+                    // (eff-posterize image 4) or (eff-posterize image [r g b a])
+                    let image  = next_or!("missing image for `posterize`");
+                    let levels = next_or!("missing levels for `posterize`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let levels = match eval_expr(env, levels)? {
+                        DataType::Number(n) => vec![n, n, n],
+                        v @ DataType::Vec(_) => match v.as_numbers() {
+                            Some(l) if l.len() == 3 || l.len() == 4 => l,
+                            _ => return err!("posterize levels must be [r g b] or [r g b a]"),
+                        },
+                        other => return err!(
+                            "levels must be of type Number or Vec, got {}", other.type_name()),
+                    };
+
+                    let mut per_channel = [0; 4];
+                    for (c, l) in per_channel.iter_mut().zip(&levels) {
+                        *c = l.max(0.0) as usize;
+                    }
+
+                    let mut new_image = image.clone();
+                    new_image.posterize(per_channel);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-threshold") => {
+                    // (eff-threshold image 128) or (eff-threshold image 'otsu)
+                    let image = next_or!("missing image for `threshold`");
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let threshold = match iter.next() {
+                        None => None,
+                        Some(item) => match eval_expr(env, item)? {
+                            DataType::Number(n) => Some(n as f32),
+                            DataType::Sym(s) if s == "otsu" || s == "auto" => None,
+                            other => return err!(
+                                "threshold must be a number or 'otsu, got {}", other.type_name()),
+                        },
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.threshold(threshold);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("eff-halftone") => {
+                    // (eff-halftone image cell screen angles)
+                    // cell and angles are a number or [c m y k]
+                    let image  = next_or!("missing image for `halftone`");
+                    let cells  = next_or!("missing cell size for `halftone`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let cells  = eval_expr(env, cells)?;
+                    let screen = check_or!(screen, Sym, "dot".to_string());
+                    let angles = match iter.next() {
+                        Some(item) => eval_expr(env, item)?,
+                        None => DataType::Vec([15.0, 75.0, 0.0, 45.0].map(DataType::Number).to_vec()),
+                    };
+
+                    let per_ink = |v: &DataType| match v {
+                        DataType::Number(n) => Some([*n; 4]),
+                        v => v.as_numbers().and_then(|n| n.try_into().ok()),
+                    };
+                    let (Some(cells), Some(angles)) = (per_ink(&cells), per_ink(&angles)) else {
+                        return err!("halftone cell size and angles must be a number or [c m y k]");
+                    };
+                    if cells.iter().any(|&c| c < 1.0) {
+                        return err!("halftone cell size must be at least 1");
+                    }
+                    let screen = match screen.as_str() {
+                        "dot" | "dots" => Screen::Dot,
+                        "line" | "lines" => Screen::Line,
+                        _ => return err!("unknown halftone screen: {}", screen),
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.halftone(screen, cells, angles);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-palette") => {
+                    // (img-palette image n method) => [[r g b] ...]
+                    let image  = next_or!("missing image for `palette`");
+                    let count  = next_or!("missing colour count for `palette`");
+
+                    let image  = check!(image, Image, eval_expr(env, image)?);
+                    let count  = check!(count, Number, eval_expr(env, count)?);
+                    let method = check_or!(method, Sym, "kmeans".to_string());
+
+                    if !(1.0..=256.0).contains(&count) {
+                        return err!("palette size must be between 1 and 256");
+                    }
+                    let method = match method.as_str() {
+                        "kmeans" | "k-means" => PaletteMethod::KMeans,
+                        "median-cut" | "median" => PaletteMethod::MedianCut,
+                        _ => return err!("unknown palette method: {}", method),
+                    };
+
+                    let palette = image.build_palette(count as usize, method)
+                        .into_iter()
+                        .map(|c| DataType::Vec(vec![
+                            DataType::Number(c[0] as f64),
+                            DataType::Number(c[1] as f64),
+                            DataType::Number(c[2] as f64),
+                        ]))
+                        .collect();
+                    Ok(DataType::Vec(palette))
+                }
+
+                List::Sym("eff-quantize") => {
+                    // (eff-quantize image 16 method dither)
+                    // (eff-quantize image [[r g b] ...] dither)
+                    let image   = next_or!("missing image for `quantize`");
+                    let palette = next_or!("missing colour count or palette for `quantize`");
+
+                    let image   = check!(image, Image, eval_expr(env, image)?);
+                    let palette = match eval_expr(env, palette)? {
+                        DataType::Number(n) => {
+                            let method = check_or!(method, Sym, "kmeans".to_string());
+                            let method = match method.as_str() {
+                                "kmeans" | "k-means" => PaletteMethod::KMeans,
+                                "median-cut" | "median" => PaletteMethod::MedianCut,
+                                _ => return err!("unknown palette method: {}", method),
+                            };
+                            if !(1.0..=256.0).contains(&n) {
+                                return err!("palette size must be between 1 and 256");
+                            }
+                            image.build_palette(n as usize, method)
+                        }
+                        DataType::Vec(colors) => {
+                            match colors.iter().map(to_color).collect::<Option<Vec<_>>>() {
+                                Some(palette) if !palette.is_empty() && palette.len() <= 256 => palette,
+                                _ => return err!("palette must be a vector of 1 to 256 colours"),
+                            }
+                        }
+                        other => return err!(
+                            "palette must be of type Number or Vec, got {}", other.type_name()),
+                    };
+                    let dither = check_or!(dither, Sym, "none".to_string());
+                    let dither = match dither.as_str() {
+                        "none" => Dither::None,
+                        "floyd-steinberg" | "fs" => Dither::FloydSteinberg,
+                        "atkinson" => Dither::Atkinson,
+                        "jarvis" | "jjn" | "jarvis-judice-ninke" => Dither::JarvisJudiceNinke,
+                        "bayer2" => Dither::Bayer(2),
+                        "bayer" | "bayer4" => Dither::Bayer(4),
+                        "bayer8" => Dither::Bayer(8),
+                        "bayer16" => Dither::Bayer(16),
+                        "blue-noise" | "blue" => Dither::BlueNoise,
+                        _ => return err!("unknown dither method: {}", dither),
+                    };
+
+                    let mut new_image = image.clone();
+                    new_image.quantize(&palette, dither);
+                    Ok(DataType::Image(new_image))
+                }
+
+                List::Sym("img-save-indexed") => {
+                    let image = next_or!("missing image for `save-indexed`");
+                    let path  = next_or!("missing path for `save-indexed`");
+                    let image = check!(image, Image, eval_expr(env, image)?);
+                    let path  = check!(path, Str, eval_expr(env, path)?);
+                    match image.to_indexed_file(&path) {
+                        Ok(()) => Ok(DataType::Nil),
+                        Err(e) => err!("failed to save indexed image: {}", e),
+                    }
+                }
+
+                List::Sym("def") => {
+                    let name  = next_or!("missing variable name for `def`");
+                    let value = next_or!("missing value for variable");
+                    let value = eval_expr(env, value)?;
+                    env.set(name.0.to_string(), value);
+                    Ok(DataType::Nil)
+                },
+
+                // (-> 1 (+ 2) ...) => (+ <1> 2)
+                List::Sym("->") => {
+                    let first = next_or!("missing first argument for `->`");
+                    let fns = iter.collect::<Vec<_>>();
+
+                    // if fns.is_empty() { return err!("empty `->`"); }
+                    if fns.is_empty() {
+                        return eval_expr(env, first);
+                    }
+
+                    let mut result_list = first;
+
+                    // transform
+                    for (f, s) in fns.into_iter() {
+                        match f {
+                            List::Sym(_) => {
+                                // (-> 1 f) => (f <1>)
+                                result_list = (List::Cons(vec![(f, s), result_list]), s);
+                            },
+                            List::Cons(xs) if xs.is_empty() => {
+                                return err!("empty function in `->`");
+                            },
+                            List::Cons(xs) => {
+                                // (-> 1 (f 2)) => (f <1> 2)
+                                let mut xs = xs.into_iter();
+                                let mut vec = vec![xs.next().unwrap(), result_list];
+                                vec.extend(xs);
+                                result_list = (List::Cons(vec), s);
+                            },
+                            e => {
+                                return err!("{} is not supported in `->`", e);
+                            }
+                        }
+                    }
+
+                    eval_expr(env, result_list)
+                },
+
+                List::Sym("+")
+                | List::Sym("-")
+                | List::Sym("*")
+                | List::Sym("/")
+                | List::Sym("%") => {
+                    let a = next_or!("missing first argument for arithmetic operation");
+                    let b = next_or!("missing second argument for arithmetic operation");
+
+                    let a = check!(a, Number, eval_expr(env, a)?);
+                    let b = check!(b, Number, eval_expr(env, b)?);
+
+                    let result = match f {
+                        List::Sym("+") => a + b,
+                        List::Sym("-") => a - b,
+                        List::Sym("*") => a * b,
+                        List::Sym("/") => {
+                            if b == 0.0 {
+                                return err!("division by zero");
+                            }
+                            a / b
+                        },
+                        List::Sym("%") => {
+                            if b == 0.0 {
+                                return err!("division by zero");
+                            }
+                            a % b
+                        },
+                        _ => unreachable!(),
+                    };
+                    Ok(DataType::Number(result))
+                }
+
+                _ => err!("unknown function: {}", f),
+            }
+        }
+        List::Vec(items) => {
+            let items = items.into_iter()
+                .map(|item| eval_expr(env, item))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(DataType::Vec(items))
         }
-        List::Vec(_items) => unimplemented!(),
     }
 }
\ No newline at end of file
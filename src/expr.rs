@@ -0,0 +1,180 @@
+use std::f64::consts::{ E, PI, TAU };
+
+use chumsky::error::Rich;
+
+use crate::parse::{ List, Spanned };
+
+/// A compiled node, reads its variables from a slice of slots
+type Node = Box<dyn Fn(&[f64]) -> f64 + Send + Sync>;
+
+/// Arithmetic expression compiled once to a tree of closures so it can be
+/// evaluated per pixel without going through the interpreter
+pub struct Expr(Node);
+
+impl Expr {
+    #[inline(always)]
+    pub fn eval(&self, slots: &[f64]) -> f64 {
+        (self.0)(slots)
+    }
+}
+
+/// What a symbol in an expression refers to
+pub enum Name {
+    /// Index into the slots passed to [`Expr::eval`]
+    Slot(usize),
+    Constant(f64),
+}
+
+fn bool(b: bool) -> f64 {
+    if b { 1.0 } else { 0.0 }
+}
+
+fn unary(a: Node, f: fn(f64) -> f64) -> Node {
+    Box::new(move |s| f(a(s)))
+}
+
+fn binary(a: Node, b: Node, f: fn(f64, f64) -> f64) -> Node {
+    Box::new(move |s| f(a(s), b(s)))
+}
+
+fn ternary(a: Node, b: Node, c: Node, f: fn(f64, f64, f64) -> f64) -> Node {
+    Box::new(move |s| f(a(s), b(s), c(s)))
+}
+
+/// Left fold of all arguments, `(- a b c)` is `(a - b) - c`
+fn fold(args: impl Iterator<Item = Node>, f: fn(f64, f64) -> f64) -> Node {
+    args.reduce(|a, b| binary(a, b, f)).expect("arity is checked")
+}
+
+/// Minimum and maximum number of arguments, `None` for unknown functions
+fn arity(name: &str) -> Option<(usize, usize)> {
+    Some(match name {
+        "+" | "-" | "*" | "min" | "max" => (1, usize::MAX),
+        "/" | "%" | "and" | "or" => (2, usize::MAX),
+        "abs" | "sqrt" | "sin" | "cos" | "tan" | "asin" | "acos" | "atan" | "exp" | "ln" | "log2"
+        | "log10" | "floor" | "ceil" | "round" | "fract" | "sign" | "not" => (1, 1),
+        "pow" | "atan2" | "hypot" | "step" | "<" | ">" | "<=" | ">=" | "=" | "!=" => (2, 2),
+        "clamp" | "mix" | "lerp" | "smoothstep" | "if" => (3, 3),
+        _ => return None,
+    })
+}
+
+fn call(name: &str, args: Vec<Node>) -> Node {
+    let mut args = args.into_iter();
+    macro_rules! arg {
+        () => { args.next().expect("arity is checked") };
+    }
+
+    match name {
+        "+" => fold(args, |a, b| a + b),
+        "*" => fold(args, |a, b| a * b),
+        "-" if args.len() == 1 => unary(arg!(), |a| -a),
+        "-" => fold(args, |a, b| a - b),
+        "/" => fold(args, |a, b| a / b),
+        "%" => fold(args, |a, b| a % b),
+        "min" => fold(args, f64::min),
+        "max" => fold(args, f64::max),
+        "and" => fold(args, |a, b| bool(a != 0.0 && b != 0.0)),
+        "or" => fold(args, |a, b| bool(a != 0.0 || b != 0.0)),
+
+        "abs" => unary(arg!(), f64::abs),
+        "sqrt" => unary(arg!(), f64::sqrt),
+        "sin" => unary(arg!(), f64::sin),
+        "cos" => unary(arg!(), f64::cos),
+        "tan" => unary(arg!(), f64::tan),
+        "asin" => unary(arg!(), f64::asin),
+        "acos" => unary(arg!(), f64::acos),
+        "atan" => unary(arg!(), f64::atan),
+        "exp" => unary(arg!(), f64::exp),
+        "ln" => unary(arg!(), f64::ln),
+        "log2" => unary(arg!(), f64::log2),
+        "log10" => unary(arg!(), f64::log10),
+        "floor" => unary(arg!(), f64::floor),
+        "ceil" => unary(arg!(), f64::ceil),
+        "round" => unary(arg!(), f64::round),
+        "fract" => unary(arg!(), |a| a - a.floor()),
+        "sign" => unary(arg!(), |a| if a == 0.0 { 0.0 } else { a.signum() }),
+        "not" => unary(arg!(), |a| bool(a == 0.0)),
+
+        "pow" => binary(arg!(), arg!(), f64::powf),
+        "atan2" => binary(arg!(), arg!(), f64::atan2),
+        "hypot" => binary(arg!(), arg!(), f64::hypot),
+        "step" => binary(arg!(), arg!(), |edge, x| bool(x >= edge)),
+        "<" => binary(arg!(), arg!(), |a, b| bool(a < b)),
+        ">" => binary(arg!(), arg!(), |a, b| bool(a > b)),
+        "<=" => binary(arg!(), arg!(), |a, b| bool(a <= b)),
+        ">=" => binary(arg!(), arg!(), |a, b| bool(a >= b)),
+        "=" => binary(arg!(), arg!(), |a, b| bool(a == b)),
+        "!=" => binary(arg!(), arg!(), |a, b| bool(a != b)),
+
+        "clamp" => ternary(arg!(), arg!(), arg!(), |x, lo, hi| x.max(lo).min(hi)),
+        "mix" | "lerp" => ternary(arg!(), arg!(), arg!(), |a, b, t| a + (b - a) * t),
+        "smoothstep" => ternary(arg!(), arg!(), arg!(), |e0, e1, x| {
+            let t = ((x - e0) / (e1 - e0)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        }),
+        // only the taken branch is evaluated
+        "if" => {
+            let (cond, then, otherwise) = (arg!(), arg!(), arg!());
+            Box::new(move |s| if cond(s) != 0.0 { then(s) } else { otherwise(s) })
+        }
+
+        _ => unreachable!("arity is checked"),
+    }
+}
+
+fn compile_node<'a>(expr: &Spanned<List>, resolve: &dyn Fn(&str) -> Option<Name>) -> Result<Node, Rich<'a, String>> {
+    let (expr, span) = expr;
+    macro_rules! err {
+        ($($arg:tt)*) => {
+            Err(Rich::custom(*span, format!($($arg)*)))
+        };
+    }
+
+    match expr {
+        List::Int(n) => {
+            let n = *n as f64;
+            Ok(Box::new(move |_| n))
+        }
+        List::Float(n) => {
+            let n = *n;
+            Ok(Box::new(move |_| n))
+        }
+        List::Sym(s) => match resolve(s) {
+            Some(Name::Slot(i)) => Ok(Box::new(move |slots| slots[i])),
+            Some(Name::Constant(n)) => Ok(Box::new(move |_| n)),
+            None => match *s {
+                "pi" => Ok(Box::new(|_| PI)),
+                "tau" => Ok(Box::new(|_| TAU)),
+                "e" => Ok(Box::new(|_| E)),
+                _ => err!("undefined symbol in expression: {}", s),
+            },
+        },
+        List::Cons(xs) => {
+            let Some(((List::Sym(name), _), args)) = xs.split_first() else {
+                return err!("expression calls must start with a function name");
+            };
+            let Some((min, max)) = arity(name) else {
+                return err!("unknown function in expression: {}", name);
+            };
+            if args.len() < min || args.len() > max {
+                return match (min, max) {
+                    (min, usize::MAX) => err!("`{}` takes at least {} arguments, got {}", name, min, args.len()),
+                    (min, _) => err!("`{}` takes {} arguments, got {}", name, min, args.len()),
+                };
+            }
+            let args = args
+                .iter()
+                .map(|arg| compile_node(arg, resolve))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(call(name, args))
+        }
+        _ => err!("{} is not supported in expressions", expr),
+    }
+}
+
+/// Compile `expr`, symbols are looked up with `resolve` first and fall
+/// back to `pi`, `tau` and `e`
+pub fn compile<'a>(expr: &Spanned<List>, resolve: &dyn Fn(&str) -> Option<Name>) -> Result<Expr, Rich<'a, String>> {
+    compile_node(expr, resolve).map(Expr)
+}
@@ -1,8 +1,80 @@
-use image::{Pixel, Rgba};
-use ndarray::prelude::*;
+use image::Rgba;
+use ndarray::{
+    parallel::prelude::*,
+    prelude::*,
+};
 
 pub mod blend;
+pub mod channel;
+pub mod compound;
 pub mod effect;
+pub mod filter;
+pub mod lut;
+pub mod mask;
+pub mod morph;
+pub mod path;
+pub mod pattern;
+pub mod quantize;
+pub mod raster;
+pub mod resample;
+pub mod stylize;
+pub mod text;
+pub mod transform;
+pub mod warp;
+
+/// How samples outside of the image bounds are resolved.
+#[derive(Clone, Copy, Debug)]
+pub enum EdgeMode {
+    Transparent,
+    Clamp,
+    Mirror,
+    Wrap,
+}
+
+impl EdgeMode {
+    /// Map a possibly out of bounds index into `0..len`, `None` means the
+    /// sample should be treated as transparent (zero). An empty axis has no
+    /// pixel to fall back to in any mode.
+    #[inline(always)]
+    pub fn index(&self, i: isize, len: usize) -> Option<usize> {
+        if i >= 0 && (i as usize) < len {
+            return Some(i as usize);
+        }
+        if len == 0 {
+            return None;
+        }
+        let n = len as isize;
+        match self {
+            EdgeMode::Transparent => None,
+            EdgeMode::Clamp => Some(i.clamp(0, n - 1) as usize),
+            EdgeMode::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                Some(if m >= n { 2 * n - 1 - m } else { m } as usize)
+            }
+            EdgeMode::Wrap => Some(i.rem_euclid(n) as usize),
+        }
+    }
+}
+
+/// Convert to floats in `0..=255` with the colour multiplied by alpha
+#[inline(always)]
+pub fn premultiply(pixel: Rgba<u8>) -> [f64; 4] {
+    let Rgba([r, g, b, a]) = pixel;
+    let alpha = a as f64 / 255.0;
+    [r as f64 * alpha, g as f64 * alpha, b as f64 * alpha, a as f64]
+}
+
+/// Inverse of [`premultiply`], rounding and clamping back to `u8`
+#[inline(always)]
+pub fn unpremultiply(pixel: [f64; 4]) -> Rgba<u8> {
+    let [r, g, b, a] = pixel;
+    if a <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let to_u8 = |c: f64| c.round().clamp(0.0, 255.0) as u8;
+    let scale = 255.0 / a;
+    Rgba([to_u8(r * scale), to_u8(g * scale), to_u8(b * scale), to_u8(a)])
+}
 
 #[derive(Clone)]
 pub struct Image {
@@ -56,7 +128,7 @@ impl Image {
     pub fn get_pixel_unchecked(&self, x: usize, y: usize) -> Rgba<u8> {
         self.image
             .get((y, x))
-            .map(|&pixel| pixel)
+            .copied()
             .unwrap()
     }
 
@@ -68,6 +140,46 @@ impl Image {
             .unwrap_or(Rgba([0, 0, 0, 0]))
     }
 
+    /// Get a pixel at coordinates that may lie outside of the image
+    #[inline(always)]
+    pub fn get_pixel_edge(&self, x: isize, y: isize, edge: EdgeMode) -> Rgba<u8> {
+        match (edge.index(x, self.width), edge.index(y, self.height)) {
+            (Some(x), Some(y)) => self.get_pixel_unchecked(x, y),
+            _ => Rgba([0, 0, 0, 0]),
+        }
+    }
+
+    /// Bilinearly sample at fractional coordinates where integer values are
+    /// pixel centers. Interpolation is done on premultiplied colour so
+    /// transparent neighbours do not bleed black into the result.
+    pub fn sample_bilinear(&self, x: f64, y: f64, edge: EdgeMode) -> Rgba<u8> {
+        unpremultiply(self.sample_bilinear_premultiplied(x, y, edge))
+    }
+
+    /// Like [`Image::sample_bilinear`] but returns the premultiplied colour
+    /// (see [`premultiply`]), useful for averaging many samples together.
+    pub fn sample_bilinear_premultiplied(&self, x: f64, y: f64, edge: EdgeMode) -> [f64; 4] {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        let taps = [
+            (self.get_pixel_edge(x0,     y0,     edge), (1.0 - fx) * (1.0 - fy)),
+            (self.get_pixel_edge(x0 + 1, y0,     edge), fx * (1.0 - fy)),
+            (self.get_pixel_edge(x0,     y0 + 1, edge), (1.0 - fx) * fy),
+            (self.get_pixel_edge(x0 + 1, y0 + 1, edge), fx * fy),
+        ];
+
+        let mut acc = [0.0; 4];
+        for (pixel, w) in taps {
+            let p = premultiply(pixel);
+            for c in 0..4 {
+                acc[c] += p[c] * w;
+            }
+        }
+        acc
+    }
+
     #[inline(always)]
     pub fn set_pixel_unchecked(&mut self, x: usize, y: usize, color: Rgba<u8>) {
         self.image
@@ -76,10 +188,92 @@ impl Image {
             .unwrap();
     }
 
+    /// Split the image into four `f32` planes (r, g, b, a) in `0..=255`.
+    pub fn to_channels(&self) -> [Array2<f32>; 4] {
+        let mut channels = [
+            Array2::<f32>::zeros((self.height, self.width)),
+            Array2::<f32>::zeros((self.height, self.width)),
+            Array2::<f32>::zeros((self.height, self.width)),
+            Array2::<f32>::zeros((self.height, self.width)),
+        ] as [Array2<f32>; 4];
+
+        for ((y, x), pixel) in self.image.indexed_iter() {
+            let Rgba([r, g, b, a]) = *pixel;
+            channels[0][[y, x]] = r as f32;
+            channels[1][[y, x]] = g as f32;
+            channels[2][[y, x]] = b as f32;
+            channels[3][[y, x]] = a as f32;
+        }
+
+        channels
+    }
+
+    /// Inverse of [`Image::to_channels`], values are rounded and clamped.
+    pub fn set_channels(&mut self, channels: &[Array2<f32>; 4]) {
+        let (height, width) = channels[0].dim();
+        self.width = width;
+        self.height = height;
+        self.image = Array2::from_shape_fn((height, width), |(y, x)| {
+            let c = |i: usize| channels[i][[y, x]].round().clamp(0.0, 255.0) as u8;
+            Rgba([c(0), c(1), c(2), c(3)])
+        });
+    }
+
+    /// Rec. 709 luminance of every pixel in `0..=255`, alpha is ignored
+    pub fn luma(&self) -> Array2<f32> {
+        self.image.map(|&Rgba([r, g, b, _])| {
+            0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32
+        })
+    }
+
+    /// Opaque greyscale image from a single plane in `0..=255`
+    pub fn from_grey(plane: &Array2<f32>) -> Self {
+        let (height, width) = plane.dim();
+        Self {
+            width,
+            height,
+            image: plane.map(|&v| {
+                let v = v.round().clamp(0.0, 255.0) as u8;
+                Rgba([v, v, v, 255])
+            }),
+        }
+    }
+
+    /// Run `f` over each of the four channel planes in parallel and write
+    /// the results back
+    pub fn map_channels<F>(&mut self, f: F)
+    where
+        F: Fn(Array2<f32>) -> Array2<f32> + Sync + Send,
+    {
+        let mapped: [Array2<f32>; 4] = self.to_channels()
+            .into_par_iter()
+            .map(f)
+            .collect::<Vec<_>>()
+            .try_into()
+            .expect("Expected 4 channels");
+
+        self.set_channels(&mapped);
+    }
+
+    /// Replace every pixel with `f(x, y, pixel)`, rows run in parallel
+    pub fn map_pixels<F>(&mut self, f: F)
+    where
+        F: Fn(usize, usize, Rgba<u8>) -> Rgba<u8> + Sync,
+    {
+        self.image.axis_iter_mut(Axis(0))
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(y, mut row)| {
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    *pixel = f(x, y, *pixel);
+                }
+            });
+    }
+
     pub fn shift_with_empty(&mut self, dx: f64, dy: f64, fract: bool) {
         let (dx, dy) = if fract {
-            ((dx as f64 * self.width  as f64).round() as isize,
-             (dy as f64 * self.height as f64).round() as isize)
+            ((dx * self.width  as f64).round() as isize,
+             (dy * self.height as f64).round() as isize)
         } else {
             (dx.round() as isize, dy.round() as isize)
         };
@@ -106,18 +300,16 @@ impl Image {
         self.image = new_image;
     }
 
-    pub fn resize_nearest_neighbour(&mut self, new_width: usize, new_height: usize) {
+    pub fn resize_nearest_neighbour(&mut self, new_width: usize, new_height: usize, edge: EdgeMode) {
         let mut resized = Array2::from_elem((new_height, new_width), Rgba([0, 0, 0, 0]));
         let x_ratio = self.width as f64 / new_width as f64;
         let y_ratio = self.height as f64 / new_height as f64;
 
         for y in 0..new_height {
             for x in 0..new_width {
-                let src_x = (x as f64 * x_ratio).floor() as usize;
-                let src_y = (y as f64 * y_ratio).floor() as usize;
-                if src_x < self.width && src_y < self.height {
-                    resized[(y, x)] = self.get_pixel_unchecked(src_x, src_y);
-                }
+                let src_x = (x as f64 * x_ratio).floor() as isize;
+                let src_y = (y as f64 * y_ratio).floor() as isize;
+                resized[(y, x)] = self.get_pixel_edge(src_x, src_y, edge);
             }
         }
 
@@ -126,48 +318,17 @@ impl Image {
         self.height = new_height;
     }
 
-    pub fn resize_bilinear(&mut self, new_width: usize, new_height: usize) {
+    pub fn resize_bilinear(&mut self, new_width: usize, new_height: usize, edge: EdgeMode) {
         let mut resized = Array2::from_elem((new_height, new_width), Rgba([0, 0, 0, 0]));
         let x_ratio = self.width as f64 / new_width as f64;
         let y_ratio = self.height as f64 / new_height as f64;
 
         for y in 0..new_height {
             for x in 0..new_width {
-                let src_x = x as f64 * x_ratio;
-                let src_y = y as f64 * y_ratio;
-
-                let x1 = src_x.floor() as usize;
-                let y1 = src_y.floor() as usize;
-                let x2 = (src_x.ceil() as usize).min(self.width - 1);
-                let y2 = (src_y.ceil() as usize).min(self.height - 1);
-
-                let a = self.get_pixel_unchecked(x1, y1);
-                let b = self.get_pixel_unchecked(x2, y1);
-                let c = self.get_pixel_unchecked(x1, y2);
-                let d = self.get_pixel_unchecked(x2, y2);
-
-                let x_diff = src_x - src_x.floor();
-                let y_diff = src_y - src_y.floor();
-
-                // Bilinear interpolation
-                let cr = (a[0] as f64 * (1.0 - x_diff) * (1.0 - y_diff) +
-                         b[0] as f64 * x_diff * (1.0 - y_diff) +
-                         c[0] as f64 * (1.0 - x_diff) * y_diff +
-                         d[0] as f64 * x_diff * y_diff) as u8;
-                let cg = (a[1] as f64 * (1.0 - x_diff) * (1.0 - y_diff) +
-                         b[1] as f64 * x_diff * (1.0 - y_diff) +
-                         c[1] as f64 * (1.0 - x_diff) * y_diff +
-                         d[1] as f64 * x_diff * y_diff) as u8;
-                let cb = (a[2] as f64 * (1.0 - x_diff) * (1.0 - y_diff) +
-                         b[2] as f64 * x_diff * (1.0 - y_diff) +
-                         c[2] as f64 * (1.0 - x_diff) * y_diff +
-                         d[2] as f64 * x_diff * y_diff) as u8;
-                let ca = (a[3] as f64 * (1.0 - x_diff) * (1.0 - y_diff) +
-                         b[3] as f64 * x_diff * (1.0 - y_diff) +
-                         c[3] as f64 * (1.0 - x_diff) * y_diff +
-                         d[3] as f64 * x_diff * y_diff) as u8;
-                resized[(y, x)] = Rgba([cr, cg, cb, ca]);
-                resized[(y, x)].apply(|c| c.clamp(0, 255));
+                // Map output pixel centers onto the source pixel grid
+                let src_x = (x as f64 + 0.5) * x_ratio - 0.5;
+                let src_y = (y as f64 + 0.5) * y_ratio - 0.5;
+                resized[(y, x)] = self.sample_bilinear(src_x, src_y, edge);
             }
         }
 
@@ -175,4 +336,4 @@ impl Image {
         self.width = new_width;
         self.height = new_height;
     }
-}
\ No newline at end of file
+}
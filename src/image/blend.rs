@@ -1,5 +1,6 @@
 use super::Image;
 use image::{Pixel, Rgb, Rgba};
+use ndarray::Array2;
 
 // B = top, A = bottom
 #[derive(Clone, Copy, Debug)]
@@ -8,6 +9,7 @@ pub enum BlendMode {
     Multiply,
     Screen,
     Overlay,
+    Add,
 }
 
 impl BlendMode {
@@ -46,29 +48,36 @@ impl BlendMode {
             (bottomf32[2] * bottomf32[3]),
         ]);
 
+        let (ta, ba) = (topf32[3], bottomf32[3]);
+        // Separable modes mix the straight colours where both layers are
+        // present and keep each layer as is where the other one is missing
+        let mut mix = |f: fn(f32, f32) -> f32| {
+            for c in 0..3 {
+                let mixed = f(bottomf32[c], topf32[c]);
+                bottom_pm[c] = bottom_pm[c] * (1.0 - ta) + top_pm[c] * (1.0 - ba) + ta * ba * mixed;
+            }
+        };
         match self {
             BlendMode::Normal => bottom_pm.apply2(&top_pm, |b, t|
-                t + b * (1.0 - topf32[3])),
-            BlendMode::Multiply => bottom_pm.apply2(&top_pm, |b, t| t * b),
-            BlendMode::Screen => bottom_pm.apply2(&top_pm, |b, t|
-                1.0 - (1.0 - t) * (1.0 - b)),
-            BlendMode::Overlay => {
-                bottom_pm.apply2(&top_pm, |b, t| {
-                    if t < 0.5 {
-                        2.0 * b * t
-                    } else {
-                        1.0 - 2.0 * (1.0 - b) * (1.0 - t)
-                    }
-                })
-            },
+                t + b * (1.0 - ta)),
+            BlendMode::Multiply => mix(|b, t| t * b),
+            BlendMode::Screen => mix(|b, t| 1.0 - (1.0 - t) * (1.0 - b)),
+            BlendMode::Add => mix(|b, t| (t + b).min(1.0)),
+            BlendMode::Overlay => mix(|b, t| {
+                if t < 0.5 {
+                    2.0 * b * t
+                } else {
+                    1.0 - 2.0 * (1.0 - b) * (1.0 - t)
+                }
+            }),
         };
 
         // Unmultiply
         let mut final_pixel = Rgba([
-            (bottom_pm[0] / alpha_final * 255.0) as u8,
-            (bottom_pm[1] / alpha_final * 255.0) as u8,
-            (bottom_pm[2] / alpha_final * 255.0) as u8,
-            (alpha_final * 255.0) as u8,
+            (bottom_pm[0] / alpha_final * 255.0).round() as u8,
+            (bottom_pm[1] / alpha_final * 255.0).round() as u8,
+            (bottom_pm[2] / alpha_final * 255.0).round() as u8,
+            (alpha_final * 255.0).round() as u8,
         ]);
         final_pixel.apply(|c| c.clamp(0, 255));
         final_pixel
@@ -76,10 +85,16 @@ impl BlendMode {
 }
 
 impl Image {
-    pub fn blend_images(&mut self, above: &Image, mode: BlendMode) {
+    /// Composite `above` over the image from the top left corner. `mask`,
+    /// in `0..=255` and the size of `above`, scales its alpha.
+    pub fn blend_images(&mut self, above: &Image, mode: BlendMode, mask: Option<&Array2<f32>>) {
         for y in 0..self.height.min(above.height) {
             for x in 0..self.width.min(above.width) {
-                let top = above.get_pixel_unchecked(x, y);
+                let mut top = above.get_pixel_unchecked(x, y);
+                if let Some(mask) = mask {
+                    let m = mask[[y, x]].clamp(0.0, 255.0) / 255.0;
+                    top[3] = (top[3] as f32 * m).round() as u8;
+                }
                 let bottom = self.get_pixel_unchecked(x, y);
 
                 let blended_pixel = mode.blend_pixel(top, bottom);
@@ -88,4 +103,19 @@ impl Image {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Half transparent black multiplied over opaque white darkens it by
+    /// half and stays opaque
+    #[test]
+    fn multiply_half_alpha_over_opaque() {
+        let out = BlendMode::Multiply.blend_pixel(Rgba([0, 0, 0, 128]), Rgba([255, 255, 255, 255]));
+        assert_eq!(out[3], 255);
+        for c in 0..3 {
+            assert!(out[c].abs_diff(127) <= 1, "got {out:?}");
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use image::Rgba;
+use ndarray::prelude::*;
+
+use super::Image;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    /// Rec. 709 luminance, alpha is ignored
+    Luma,
+}
+
+/// Where a channel of a swizzled pixel comes from
+#[derive(Clone, Copy, Debug)]
+pub enum Swizzle {
+    /// Index into the source pixel, `0..4` for r, g, b, a
+    Take(usize),
+    Zero,
+    Full,
+}
+
+impl Swizzle {
+    /// Parse a pattern of `r`, `g`, `b`, `a`, `0` and `1` like `bgra`. Three
+    /// letter patterns keep the original alpha.
+    pub fn parse(pattern: &str) -> Option<[Swizzle; 4]> {
+        let mut channels = pattern
+            .chars()
+            .map(|c| match c {
+                'r' => Some(Swizzle::Take(0)),
+                'g' => Some(Swizzle::Take(1)),
+                'b' => Some(Swizzle::Take(2)),
+                'a' => Some(Swizzle::Take(3)),
+                '0' => Some(Swizzle::Zero),
+                '1' => Some(Swizzle::Full),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if channels.len() == 3 {
+            channels.push(Swizzle::Take(3));
+        }
+        channels.try_into().ok()
+    }
+}
+
+impl Image {
+    /// A single channel as a plane in `0..=255`
+    pub fn channel(&self, channel: Channel) -> Array2<f32> {
+        self.image.map(|&Rgba([r, g, b, a])| match channel {
+            Channel::Red => r as f32,
+            Channel::Green => g as f32,
+            Channel::Blue => b as f32,
+            Channel::Alpha => a as f32,
+            Channel::Luma => 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32,
+        })
+    }
+
+    /// Reorder, duplicate or replace channels
+    pub fn swizzle(&mut self, pattern: [Swizzle; 4]) {
+        self.image.par_map_inplace(|pixel| {
+            let source = pixel.0;
+            pixel.0 = pattern.map(|s| match s {
+                Swizzle::Take(i) => source[i],
+                Swizzle::Zero => 0,
+                Swizzle::Full => 255,
+            });
+        });
+    }
+
+    /// Recombine straight colour with a row major 4x5 matrix, each output
+    /// channel is the weighted sum of r, g, b, a in `0..=1` plus an offset
+    pub fn channel_mixer(&mut self, matrix: &[f64; 20]) {
+        self.image.par_map_inplace(|pixel| {
+            let source = pixel.0.map(|c| c as f64 / 255.0);
+            let mut out = [0; 4];
+            for (i, row) in matrix.chunks_exact(5).enumerate() {
+                let v = (0..4).map(|c| row[c] * source[c]).sum::<f64>() + row[4];
+                out[i] = (v * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+            pixel.0 = out;
+        });
+    }
+}
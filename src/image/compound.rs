@@ -0,0 +1,108 @@
+use image::Rgba;
+use ndarray::{
+    Zip,
+    prelude::*,
+};
+
+use super::{ EdgeMode, Image, unpremultiply };
+use super::blend::BlendMode;
+use super::effect::blur_plane;
+use super::morph::{ Element, morph_plane };
+
+/// Glow parameters shared by `eff-glow` and `eff-bloom`
+#[derive(Clone, Copy, Debug)]
+pub struct Glow {
+    /// Luminance (`0..=255`) below which pixels do not contribute
+    pub threshold: f32,
+    pub radius: usize,
+    pub intensity: f32,
+    pub tint: Rgba<u8>,
+    /// How the glow is laid over the image, usually screen or add
+    pub blend: BlendMode,
+    /// Number of blur levels summed together, each half the radius of the last
+    pub levels: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DropShadow {
+    pub dx: isize,
+    pub dy: isize,
+    pub radius: usize,
+    pub color: Rgba<u8>,
+    pub opacity: f32,
+    /// Grow the silhouette by this many pixels before blurring
+    pub spread: usize,
+}
+
+impl Image {
+    /// Bright pass, blur and composite in one go: only the four blurred
+    /// planes are allocated, the result is written straight into `self`
+    pub fn glow(&mut self, glow: &Glow) {
+        let knee = (255.0 - glow.threshold).max(1.0);
+
+        // Premultiplied bright pass, soft thresholded on luminance
+        let mut bright = [
+            Array2::<f32>::zeros((self.height, self.width)),
+            Array2::<f32>::zeros((self.height, self.width)),
+            Array2::<f32>::zeros((self.height, self.width)),
+            Array2::<f32>::zeros((self.height, self.width)),
+        ];
+        for ((y, x), pixel) in self.image.indexed_iter() {
+            let Rgba([r, g, b, a]) = *pixel;
+            let luma = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+            let weight = ((luma - glow.threshold) / knee).clamp(0.0, 1.0) * a as f32 / 255.0;
+            bright[0][[y, x]] = r as f32 * weight;
+            bright[1][[y, x]] = g as f32 * weight;
+            bright[2][[y, x]] = b as f32 * weight;
+            bright[3][[y, x]] = 255.0 * weight;
+        }
+
+        let levels = glow.levels.max(1);
+        let blurred = bright.map(|plane| {
+            let mut sum = Array2::<f32>::zeros(plane.dim());
+            for level in 0..levels {
+                let radius = glow.radius >> level;
+                sum += &blur_plane(&plane, radius, EdgeMode::Transparent);
+            }
+            sum / levels as f32
+        });
+
+        let tint = [0, 1, 2].map(|c| glow.tint[c] as f64 / 255.0 * glow.intensity as f64);
+        Zip::indexed(&mut self.image).par_for_each(|(y, x), pixel| {
+            let light = unpremultiply([
+                blurred[0][[y, x]] as f64 * tint[0],
+                blurred[1][[y, x]] as f64 * tint[1],
+                blurred[2][[y, x]] as f64 * tint[2],
+                (blurred[3][[y, x]] as f64 * glow.intensity as f64).min(255.0),
+            ]);
+            *pixel = glow.blend.blend_pixel(light, *pixel);
+        });
+    }
+
+    /// Composite the image over a blurred, coloured and offset copy of its
+    /// own silhouette. The image keeps its size, shadow outside is clipped.
+    pub fn drop_shadow(&mut self, shadow: &DropShadow) {
+        let mut alpha = self.image.map(|p| p[3] as f32);
+        if shadow.spread > 0 {
+            alpha = morph_plane(&alpha, &Element::Disk(shadow.spread), true);
+        }
+        let alpha = blur_plane(&alpha, shadow.radius, EdgeMode::Transparent);
+
+        let (width, height) = (self.width, self.height);
+        let color = shadow.color;
+        let strength = shadow.opacity.clamp(0.0, 1.0) * color[3] as f32 / 255.0;
+
+        Zip::indexed(&mut self.image).par_for_each(|(y, x), pixel| {
+            let sx = x as isize - shadow.dx;
+            let sy = y as isize - shadow.dy;
+            let a = if sx < 0 || sy < 0 || sx >= width as isize || sy >= height as isize {
+                0.0
+            } else {
+                alpha[[sy as usize, sx as usize]] * strength
+            };
+
+            let below = Rgba([color[0], color[1], color[2], a.round().clamp(0.0, 255.0) as u8]);
+            *pixel = BlendMode::Normal.blend_pixel(*pixel, below);
+        });
+    }
+}
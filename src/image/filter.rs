@@ -0,0 +1,371 @@
+use image::Rgba;
+use ndarray::{
+    parallel::prelude::*,
+    prelude::*,
+};
+
+use super::{ EdgeMode, Image };
+use super::effect::{ convolve_2d, gaussian_plane };
+
+/// Below this radius the median is found by sorting the window, above it a
+/// sliding histogram is used so the cost per pixel grows with `r` not `r^2`
+const MEDIAN_HISTOGRAM_RADIUS: usize = 2;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Kuwahara {
+    /// Four square quadrants, pick the one with the lowest variance
+    Classic,
+    /// Eight weighted sectors of an ellipse aligned to the local structure
+    Anisotropic,
+}
+
+/// Summed area table with an extra leading row and column of zeros
+fn integral(plane: &Array2<f64>) -> Array2<f64> {
+    let (height, width) = plane.dim();
+    let mut sat = Array2::<f64>::zeros((height + 1, width + 1));
+    for y in 0..height {
+        let mut row = 0.0;
+        for x in 0..width {
+            row += plane[[y, x]];
+            sat[[y + 1, x + 1]] = sat[[y, x + 1]] + row;
+        }
+    }
+    sat
+}
+
+/// Sum of the rectangle `x0..x1`, `y0..y1` from a summed area table
+#[inline(always)]
+fn area_sum(sat: &Array2<f64>, x0: usize, y0: usize, x1: usize, y1: usize) -> f64 {
+    sat[[y1, x1]] - sat[[y0, x1]] - sat[[y1, x0]] + sat[[y0, x0]]
+}
+
+impl Image {
+    /// Per channel median over a `(2 * radius + 1)` square, clamping at edges
+    pub fn median(&mut self, radius: usize) {
+        if radius == 0 || self.width == 0 || self.height == 0 {
+            return;
+        }
+        let (width, height) = (self.width, self.height);
+        let r = radius as isize;
+        let window = (2 * radius + 1) * (2 * radius + 1);
+        let source = &self.image;
+        let at = |x: isize, y: isize| {
+            let x = EdgeMode::Clamp.index(x, width).unwrap();
+            let y = EdgeMode::Clamp.index(y, height).unwrap();
+            source[[y, x]]
+        };
+
+        let mut output = Array2::from_elem((height, width), Rgba([0, 0, 0, 0]));
+        if radius <= MEDIAN_HISTOGRAM_RADIUS {
+            output.axis_iter_mut(Axis(0))
+                .into_par_iter()
+                .enumerate()
+                .for_each(|(y, mut out_row)| {
+                    let mut values = vec![[0u8; 4]; window];
+                    for x in 0..width {
+                        let mut i = 0;
+                        for dy in -r..=r {
+                            for dx in -r..=r {
+                                values[i] = at(x as isize + dx, y as isize + dy).0;
+                                i += 1;
+                            }
+                        }
+                        let mut out = [0u8; 4];
+                        let mut channel = vec![0u8; window];
+                        for (c, o) in out.iter_mut().enumerate() {
+                            for (v, p) in channel.iter_mut().zip(&values) {
+                                *v = p[c];
+                            }
+                            *o = *channel.select_nth_unstable(window / 2).1;
+                        }
+                        out_row[x] = Rgba(out);
+                    }
+                });
+        } else {
+            // Huang's algorithm: slide a 256 bin histogram along each row,
+            // only the entering and leaving columns are touched per step
+            output.axis_iter_mut(Axis(0))
+                .into_par_iter()
+                .enumerate()
+                .for_each(|(y, mut out_row)| {
+                    let y = y as isize;
+                    let mut hist = [[0u32; 256]; 4];
+                    for dy in -r..=r {
+                        for dx in -r..=r {
+                            let p = at(dx, y + dy);
+                            for c in 0..4 {
+                                hist[c][p[c] as usize] += 1;
+                            }
+                        }
+                    }
+
+                    for x in 0..width as isize {
+                        let mut out = [0u8; 4];
+                        for (c, o) in out.iter_mut().enumerate() {
+                            let mut seen = 0;
+                            for (v, count) in hist[c].iter().enumerate() {
+                                seen += *count as usize;
+                                if seen > window / 2 {
+                                    *o = v as u8;
+                                    break;
+                                }
+                            }
+                        }
+                        out_row[x as usize] = Rgba(out);
+
+                        for dy in -r..=r {
+                            let leaving = at(x - r, y + dy);
+                            let entering = at(x + r + 1, y + dy);
+                            for c in 0..4 {
+                                hist[c][leaving[c] as usize] -= 1;
+                                hist[c][entering[c] as usize] += 1;
+                            }
+                        }
+                    }
+                });
+        }
+
+        self.image = output;
+    }
+
+    /// Bilateral filter, neighbours are weighted by their distance
+    /// (`sigma_spatial`, in pixels) and by their colour difference
+    /// (`sigma_range`, in `0..=255` units)
+    pub fn bilateral(&mut self, sigma_spatial: f32, sigma_range: f32) {
+        if sigma_spatial <= 0.0 || sigma_range <= 0.0 {
+            return;
+        }
+        let (width, height) = (self.width, self.height);
+        let radius = (2.0 * sigma_spatial).ceil() as isize;
+        let size = (2 * radius + 1) as usize;
+        let spatial = Array2::from_shape_fn((size, size), |(y, x)| {
+            let (dx, dy) = (x as f32 - radius as f32, y as f32 - radius as f32);
+            (-(dx * dx + dy * dy) / (2.0 * sigma_spatial * sigma_spatial)).exp()
+        });
+        let range_scale = -1.0 / (2.0 * sigma_range * sigma_range);
+        let source = &self.image;
+
+        let mut output = Array2::from_elem((height, width), Rgba([0, 0, 0, 0]));
+        output.axis_iter_mut(Axis(0))
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(y, mut out_row)| {
+                for x in 0..width {
+                    let center = source[[y, x]];
+                    let mut acc = [0.0f32; 4];
+                    let mut total = 0.0;
+                    for dy in -radius..=radius {
+                        let Some(sy) = EdgeMode::Clamp.index(y as isize + dy, height) else { continue };
+                        for dx in -radius..=radius {
+                            let Some(sx) = EdgeMode::Clamp.index(x as isize + dx, width) else { continue };
+                            let p = source[[sy, sx]];
+                            let d2 = (0..3)
+                                .map(|c| (p[c] as f32 - center[c] as f32).powi(2))
+                                .sum::<f32>();
+                            let w = spatial[[(dy + radius) as usize, (dx + radius) as usize]]
+                                * (d2 * range_scale).exp();
+                            for c in 0..4 {
+                                acc[c] += p[c] as f32 * w;
+                            }
+                            total += w;
+                        }
+                    }
+                    out_row[x] = Rgba(acc.map(|c| (c / total).round().clamp(0.0, 255.0) as u8));
+                }
+            });
+
+        self.image = output;
+    }
+
+    pub fn kuwahara(&mut self, radius: usize, kind: Kuwahara) {
+        if radius == 0 {
+            return;
+        }
+        match kind {
+            Kuwahara::Classic => self.kuwahara_classic(radius),
+            Kuwahara::Anisotropic => self.kuwahara_anisotropic(radius),
+        }
+    }
+
+    fn kuwahara_classic(&mut self, radius: usize) {
+        let (width, height) = (self.width, self.height);
+        let channels = self.to_channels().map(|c| c.mapv(|v| v as f64));
+        let luma = self.luma().mapv(|v| v as f64);
+        let sums = channels.each_ref().map(integral);
+        let luma_sum = integral(&luma);
+        let luma_sq = integral(&luma.mapv(|v| v * v));
+
+        let mut output = Array2::from_elem((height, width), Rgba([0, 0, 0, 0]));
+        output.axis_iter_mut(Axis(0))
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(y, mut out_row)| {
+                for x in 0..width {
+                    // quadrants are inclusive of the center row and column,
+                    // clipped to the image
+                    let x0 = x.saturating_sub(radius);
+                    let y0 = y.saturating_sub(radius);
+                    let x1 = (x + radius + 1).min(width);
+                    let y1 = (y + radius + 1).min(height);
+                    let quadrants = [
+                        (x0, y0, x + 1, y + 1),
+                        (x, y0, x1, y + 1),
+                        (x0, y, x + 1, y1),
+                        (x, y, x1, y1),
+                    ];
+
+                    let (mut best, mut best_var) = (quadrants[0], f64::INFINITY);
+                    for q @ (qx0, qy0, qx1, qy1) in quadrants {
+                        let n = ((qx1 - qx0) * (qy1 - qy0)) as f64;
+                        let mean = area_sum(&luma_sum, qx0, qy0, qx1, qy1) / n;
+                        let var = area_sum(&luma_sq, qx0, qy0, qx1, qy1) / n - mean * mean;
+                        if var < best_var {
+                            best = q;
+                            best_var = var;
+                        }
+                    }
+
+                    let (qx0, qy0, qx1, qy1) = best;
+                    let n = ((qx1 - qx0) * (qy1 - qy0)) as f64;
+                    out_row[x] = Rgba(sums.each_ref().map(|sat| {
+                        (area_sum(sat, qx0, qy0, qx1, qy1) / n).round().clamp(0.0, 255.0) as u8
+                    }));
+                }
+            });
+
+        self.image = output;
+    }
+
+    /// Anisotropic Kuwahara filter with polynomial sector weights after
+    /// Kyprianidis et al. The filter shape is stretched along the local
+    /// orientation estimated from a smoothed structure tensor.
+    fn kuwahara_anisotropic(&mut self, radius: usize) {
+        const SECTORS: usize = 8;
+        const SHARPNESS: f32 = 8.0;
+        const ALPHA: f32 = 1.0;
+
+        let (width, height) = (self.width, self.height);
+        let channels = self.to_channels().map(|c| c / 255.0);
+
+        // Structure tensor summed over the colour channels
+        let sobel_x = array![[-1.0, 0.0, 1.0], [-2.0, 0.0, 2.0], [-1.0, 0.0, 1.0]] / 4.0;
+        let sobel_y = sobel_x.t().to_owned();
+        let mut tensor = [
+            Array2::<f32>::zeros((height, width)),
+            Array2::<f32>::zeros((height, width)),
+            Array2::<f32>::zeros((height, width)),
+        ];
+        for channel in &channels[..3] {
+            let gx = convolve_2d(channel, &sobel_x, EdgeMode::Clamp);
+            let gy = convolve_2d(channel, &sobel_y, EdgeMode::Clamp);
+            tensor[0] += &(&gx * &gx);
+            tensor[1] += &(&gx * &gy);
+            tensor[2] += &(&gy * &gy);
+        }
+        let tensor = tensor.map(|t| gaussian_plane(&t, 2.0, EdgeMode::Clamp));
+
+        let zeta = 2.0 / radius as f32;
+        let eta = {
+            let step = std::f32::consts::PI / SECTORS as f32;
+            (zeta + step.cos()) / (step.sin() * step.sin())
+        };
+        let radius = radius as f32;
+
+        let mut output = Array2::from_elem((height, width), Rgba([0, 0, 0, 0]));
+        output.axis_iter_mut(Axis(0))
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(y, mut out_row)| {
+                for x in 0..width {
+                    let (e, f, g) = (tensor[0][[y, x]], tensor[1][[y, x]], tensor[2][[y, x]]);
+                    let root = ((e - g) * (e - g) + 4.0 * f * f).sqrt();
+                    let l1 = (e + g + root) / 2.0;
+                    let l2 = (e + g - root) / 2.0;
+                    let (tx, ty) = (l1 - e, -f);
+                    let phi = if tx == 0.0 && ty == 0.0 { 0.0 } else { ty.atan2(tx) };
+                    let anisotropy = if l1 + l2 > 0.0 { (l1 - l2) / (l1 + l2) } else { 0.0 };
+
+                    let a = radius * ((ALPHA + anisotropy) / ALPHA).clamp(0.1, 2.0);
+                    let b = radius * (ALPHA / (ALPHA + anisotropy)).clamp(0.1, 2.0);
+                    let (sin, cos) = phi.sin_cos();
+                    let max_x = (a * a * cos * cos + b * b * sin * sin).sqrt().ceil() as isize;
+                    let max_y = (a * a * sin * sin + b * b * cos * cos).sqrt().ceil() as isize;
+
+                    let mut mean = [[0.0f32; 4]; SECTORS];
+                    let mut sq = [[0.0f32; 3]; SECTORS];
+                    let mut total = [0.0f32; SECTORS];
+
+                    for dy in -max_y..=max_y {
+                        for dx in -max_x..=max_x {
+                            // rotate into the ellipse frame and scale to the unit disk
+                            let (fx, fy) = (dx as f32, dy as f32);
+                            let vx = (cos * fx + sin * fy) / a;
+                            let vy = (-sin * fx + cos * fy) / b;
+                            let d2 = vx * vx + vy * vy;
+                            if d2 > 1.0 {
+                                continue;
+                            }
+                            let sx = EdgeMode::Clamp.index(x as isize + dx, width).unwrap();
+                            let sy = EdgeMode::Clamp.index(y as isize + dy, height).unwrap();
+                            let p = [0, 1, 2, 3].map(|c| channels[c][[sy, sx]]);
+
+                            let mut w = [0.0f32; SECTORS];
+                            let mut sum = 0.0;
+                            let mut sector_weights = |vx: f32, vy: f32, first: usize| {
+                                let vxx = zeta - eta * vx * vx;
+                                let vyy = zeta - eta * vy * vy;
+                                for (k, z) in [vy + vxx, -vx + vyy, -vy + vxx, vx + vyy].into_iter().enumerate() {
+                                    let z = z.max(0.0);
+                                    w[first + 2 * k] = z * z;
+                                    sum += z * z;
+                                }
+                            };
+                            sector_weights(vx, vy, 0);
+                            let s = std::f32::consts::FRAC_1_SQRT_2;
+                            sector_weights(s * (vx - vy), s * (vx + vy), 1);
+                            if sum <= 0.0 {
+                                continue;
+                            }
+
+                            let falloff = (-3.125 * d2).exp() / sum;
+                            for k in 0..SECTORS {
+                                let wk = w[k] * falloff;
+                                for c in 0..4 {
+                                    mean[k][c] += p[c] * wk;
+                                }
+                                for c in 0..3 {
+                                    sq[k][c] += p[c] * p[c] * wk;
+                                }
+                                total[k] += wk;
+                            }
+                        }
+                    }
+
+                    let mut out = [0.0f32; 4];
+                    let mut out_total = 0.0;
+                    for k in 0..SECTORS {
+                        if total[k] <= 0.0 {
+                            continue;
+                        }
+                        let m = mean[k].map(|v| v / total[k]);
+                        let sigma2 = (0..3)
+                            .map(|c| (sq[k][c] / total[k] - m[c] * m[c]).abs())
+                            .sum::<f32>();
+                        let w = 1.0 / (1.0 + (255.0 * sigma2).powf(SHARPNESS / 2.0));
+                        for c in 0..4 {
+                            out[c] += m[c] * w;
+                        }
+                        out_total += w;
+                    }
+
+                    out_row[x] = if out_total > 0.0 {
+                        Rgba(out.map(|v| (v / out_total * 255.0).round().clamp(0.0, 255.0) as u8))
+                    } else {
+                        self.image[[y, x]]
+                    };
+                }
+            });
+
+        self.image = output;
+    }
+}
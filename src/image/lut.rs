@@ -0,0 +1,286 @@
+use image::Rgba;
+use ndarray::prelude::*;
+
+use super::Image;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LutKind {
+    OneD,
+    ThreeD,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LutInterp {
+    Trilinear,
+    Tetrahedral,
+}
+
+/// A colour lookup table as described by the Adobe/Resolve `.cube` format.
+///
+/// 3D tables are stored with red changing fastest, then green, then blue,
+/// which is the same order the entries appear in the file.
+#[derive(Clone)]
+pub struct Lut {
+    pub title: Option<String>,
+    pub kind: LutKind,
+    pub size: usize,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub data: Vec<[f32; 3]>,
+}
+
+impl std::fmt::Debug for Lut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Lut {{ kind: {:?}, size: {} }}", self.kind, self.size)
+    }
+}
+
+fn parse_triple(parts: &[&str], line: usize) -> Result<[f32; 3], String> {
+    if parts.len() != 3 {
+        return Err(format!("line {}: expected 3 values, got {}", line, parts.len()));
+    }
+    let mut out = [0.0; 3];
+    for (o, p) in out.iter_mut().zip(parts) {
+        *o = p.parse()
+            .map_err(|_| format!("line {}: invalid number `{}`", line, p))?;
+    }
+    Ok(out)
+}
+
+impl Lut {
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let src = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&src)
+    }
+
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut title = None;
+        let mut kind = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut data = Vec::new();
+
+        for (i, line) in src.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parts = line.split_whitespace().collect::<Vec<_>>();
+            match parts[0] {
+                "TITLE" => {
+                    title = Some(line["TITLE".len()..].trim().trim_matches('"').to_string());
+                }
+                "LUT_1D_SIZE" | "LUT_3D_SIZE" => {
+                    let size = parts.get(1)
+                        .and_then(|s| s.parse::<usize>().ok())
+                        .filter(|&s| s >= 2)
+                        .ok_or(format!("line {}: invalid LUT size", line_no))?;
+                    kind = Some(if parts[0] == "LUT_1D_SIZE" {
+                        (LutKind::OneD, size)
+                    } else {
+                        (LutKind::ThreeD, size)
+                    });
+                }
+                "DOMAIN_MIN" => domain_min = parse_triple(&parts[1..], line_no)?,
+                "DOMAIN_MAX" => domain_max = parse_triple(&parts[1..], line_no)?,
+                // Resolve writes its domain as a single range for all channels
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                    let range = parts[1..].iter()
+                        .map(|p| p.parse::<f32>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .ok()
+                        .filter(|r| r.len() == 2)
+                        .ok_or(format!("line {}: invalid input range", line_no))?;
+                    domain_min = [range[0]; 3];
+                    domain_max = [range[1]; 3];
+                }
+                // Skip keywords we do not understand rather than failing
+                k if k.starts_with(|c: char| c.is_ascii_alphabetic()) => continue,
+                _ => data.push(parse_triple(&parts, line_no)?),
+            }
+        }
+
+        let (kind, size) = kind.ok_or("missing LUT_1D_SIZE or LUT_3D_SIZE")?;
+        let expected = match kind {
+            LutKind::OneD => size,
+            LutKind::ThreeD => size * size * size,
+        };
+        if data.len() != expected {
+            return Err(format!("expected {} table entries, got {}", expected, data.len()));
+        }
+
+        Ok(Self { title, kind, size, domain_min, domain_max, data })
+    }
+
+    pub fn to_file(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_cube_string()).map_err(|e| e.to_string())
+    }
+
+    pub fn to_cube_string(&self) -> String {
+        let mut out = String::new();
+        if let Some(title) = &self.title {
+            out.push_str(&format!("TITLE \"{}\"\n", title));
+        }
+        match self.kind {
+            LutKind::OneD => out.push_str(&format!("LUT_1D_SIZE {}\n", self.size)),
+            LutKind::ThreeD => out.push_str(&format!("LUT_3D_SIZE {}\n", self.size)),
+        }
+        let [r0, g0, b0] = self.domain_min;
+        let [r1, g1, b1] = self.domain_max;
+        out.push_str(&format!("DOMAIN_MIN {:.6} {:.6} {:.6}\n", r0, g0, b0));
+        out.push_str(&format!("DOMAIN_MAX {:.6} {:.6} {:.6}\n", r1, g1, b1));
+        for [r, g, b] in &self.data {
+            out.push_str(&format!("{:.6} {:.6} {:.6}\n", r, g, b));
+        }
+        out
+    }
+
+    #[inline(always)]
+    fn at(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.data[r + self.size * (g + self.size * b)]
+    }
+
+    /// Look up a colour in `[0, 1]` (before the domain is applied).
+    pub fn lookup(&self, rgb: [f32; 3], interp: LutInterp) -> [f32; 3] {
+        let n = (self.size - 1) as f32;
+        let mut pos = [0.0; 3];
+        for c in 0..3 {
+            let range = self.domain_max[c] - self.domain_min[c];
+            let t = if range == 0.0 { 0.0 } else { (rgb[c] - self.domain_min[c]) / range };
+            pos[c] = t.clamp(0.0, 1.0) * n;
+        }
+
+        match self.kind {
+            LutKind::OneD => {
+                let mut out = [0.0; 3];
+                for c in 0..3 {
+                    let i0 = pos[c].floor() as usize;
+                    let i1 = (i0 + 1).min(self.size - 1);
+                    let f = pos[c] - i0 as f32;
+                    out[c] = self.data[i0][c] * (1.0 - f) + self.data[i1][c] * f;
+                }
+                out
+            }
+            LutKind::ThreeD => {
+                let i0 = pos.map(|p| (p.floor() as usize).min(self.size - 1));
+                let i1 = i0.map(|i| (i + 1).min(self.size - 1));
+                let [fr, fg, fb] = [0, 1, 2].map(|c| pos[c] - i0[c] as f32);
+                let [r0, g0, b0] = i0;
+                let [r1, g1, b1] = i1;
+
+                let c000 = self.at(r0, g0, b0);
+                let c111 = self.at(r1, g1, b1);
+
+                let mut out = [0.0; 3];
+                match interp {
+                    LutInterp::Trilinear => {
+                        let c100 = self.at(r1, g0, b0);
+                        let c010 = self.at(r0, g1, b0);
+                        let c110 = self.at(r1, g1, b0);
+                        let c001 = self.at(r0, g0, b1);
+                        let c101 = self.at(r1, g0, b1);
+                        let c011 = self.at(r0, g1, b1);
+                        for c in 0..3 {
+                            let c00 = c000[c] * (1.0 - fr) + c100[c] * fr;
+                            let c10 = c010[c] * (1.0 - fr) + c110[c] * fr;
+                            let c01 = c001[c] * (1.0 - fr) + c101[c] * fr;
+                            let c11 = c011[c] * (1.0 - fr) + c111[c] * fr;
+                            let c0 = c00 * (1.0 - fg) + c10 * fg;
+                            let c1 = c01 * (1.0 - fg) + c11 * fg;
+                            out[c] = c0 * (1.0 - fb) + c1 * fb;
+                        }
+                    }
+                    LutInterp::Tetrahedral => {
+                        // Pick one of the six tetrahedra making up the cube
+                        // based on the ordering of the fractional parts
+                        let (a, b, wa, wb, w0, w1) = if fr > fg {
+                            if fg > fb {
+                                (self.at(r1, g0, b0), self.at(r1, g1, b0), fr - fg, fg - fb, 1.0 - fr, fb)
+                            } else if fr > fb {
+                                (self.at(r1, g0, b0), self.at(r1, g0, b1), fr - fb, fb - fg, 1.0 - fr, fg)
+                            } else {
+                                (self.at(r0, g0, b1), self.at(r1, g0, b1), fb - fr, fr - fg, 1.0 - fb, fg)
+                            }
+                        } else if fb > fg {
+                            (self.at(r0, g0, b1), self.at(r0, g1, b1), fb - fg, fg - fr, 1.0 - fb, fr)
+                        } else if fb > fr {
+                            (self.at(r0, g1, b0), self.at(r0, g1, b1), fg - fb, fb - fr, 1.0 - fg, fr)
+                        } else {
+                            (self.at(r0, g1, b0), self.at(r1, g1, b0), fg - fr, fr - fb, 1.0 - fg, fb)
+                        };
+                        for c in 0..3 {
+                            out[c] = w0 * c000[c] + wa * a[c] + wb * b[c] + w1 * c111[c];
+                        }
+                    }
+                }
+                out
+            }
+        }
+    }
+
+    /// Build a 3D LUT by reading back an identity lattice produced by
+    /// [`Image::lut_identity`] after it has been run through a colour chain.
+    pub fn from_identity_image(image: &Image) -> Result<Self, String> {
+        let size = image.height;
+        if size < 2 || image.width != size * size {
+            return Err(format!(
+                "expected a {}x{} identity lattice, got {}x{}",
+                size * size, size, image.width, image.height,
+            ));
+        }
+
+        let mut data = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let Rgba([pr, pg, pb, _]) = image.get_pixel_unchecked(b * size + r, g);
+                    data.push([pr as f32 / 255.0, pg as f32 / 255.0, pb as f32 / 255.0]);
+                }
+            }
+        }
+
+        Ok(Self {
+            title: None,
+            kind: LutKind::ThreeD,
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            data,
+        })
+    }
+}
+
+impl Image {
+    /// Identity lattice for a `size`^3 LUT, laid out as `size` slices of
+    /// `size`x`size` (red across, green down), one slice per blue step.
+    pub fn lut_identity(size: usize) -> Self {
+        let n = (size - 1).max(1) as f32;
+        let image = Array2::from_shape_fn((size, size * size), |(g, x)| {
+            let (b, r) = (x / size, x % size);
+            Rgba([
+                (r as f32 / n * 255.0).round() as u8,
+                (g as f32 / n * 255.0).round() as u8,
+                (b as f32 / n * 255.0).round() as u8,
+                255,
+            ])
+        });
+
+        Self {
+            width: size * size,
+            height: size,
+            image,
+        }
+    }
+
+    pub fn apply_lut(&mut self, lut: &Lut, interp: LutInterp) {
+        self.image.par_map_inplace(|pixel| {
+            let Rgba([r, g, b, a]) = *pixel;
+            let rgb = [r, g, b].map(|c| c as f32 / 255.0);
+            let [r, g, b] = lut.lookup(rgb, interp)
+                .map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8);
+            *pixel = Rgba([r, g, b, a]);
+        });
+    }
+}
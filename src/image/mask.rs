@@ -0,0 +1,88 @@
+use image::Rgba;
+use ndarray::{
+    Zip,
+    prelude::*,
+};
+
+use super::{ EdgeMode, Image, premultiply, unpremultiply };
+use super::effect::blur_plane;
+
+/// Which part of an image is read as a mask
+#[derive(Clone, Copy, Debug)]
+pub enum MaskSource {
+    /// Rec. 709 luminance, white keeps and black removes
+    Luma,
+    Alpha,
+}
+
+impl Image {
+    /// Mask values of the image in `0..=255`, stretched bilinearly over a
+    /// `width` by `height` area when the sizes differ
+    pub fn mask_plane(&self, width: usize, height: usize, source: MaskSource) -> Array2<f32> {
+        let value = |pixel: Rgba<u8>| match source {
+            MaskSource::Luma => 0.2126 * pixel[0] as f32 + 0.7152 * pixel[1] as f32 + 0.0722 * pixel[2] as f32,
+            MaskSource::Alpha => pixel[3] as f32,
+        };
+        if (width, height) == (self.width, self.height) {
+            return self.image.map(|&p| value(p));
+        }
+
+        let sx = self.width as f64 / width as f64;
+        let sy = self.height as f64 / height as f64;
+        let mut plane = Array2::zeros((height, width));
+        Zip::indexed(&mut plane).par_for_each(|(y, x), v| {
+            let pixel = self.sample_bilinear((x as f64 + 0.5) * sx - 0.5, (y as f64 + 0.5) * sy - 0.5, EdgeMode::Clamp);
+            *v = value(pixel);
+        });
+        plane
+    }
+
+    /// The alpha channel as a plane in `0..=255`
+    pub fn alpha_plane(&self) -> Array2<f32> {
+        self.image.map(|p| p[3] as f32)
+    }
+
+    /// Replace the alpha channel, `alpha` must match the image size
+    pub fn set_alpha(&mut self, alpha: &Array2<f32>) {
+        Zip::from(&mut self.image).and(alpha).par_for_each(|pixel, &a| {
+            pixel[3] = a.round().clamp(0.0, 255.0) as u8;
+        });
+    }
+
+    /// Multiply the alpha channel by `mask`, which must match the image size
+    pub fn apply_mask(&mut self, mask: &Array2<f32>) {
+        Zip::from(&mut self.image).and(mask).par_for_each(|pixel, &m| {
+            let a = pixel[3] as f32 * m.clamp(0.0, 255.0) / 255.0;
+            pixel[3] = a.round() as u8;
+        });
+    }
+
+    pub fn invert_alpha(&mut self) {
+        self.image.par_map_inplace(|pixel| pixel[3] = 255 - pixel[3]);
+    }
+
+    /// Soften the edges of the alpha channel with a Gaussian blur, colour is
+    /// left as is. Image borders are extended so they do not fade out.
+    pub fn feather(&mut self, radius: usize) {
+        let alpha = blur_plane(&self.alpha_plane(), radius, EdgeMode::Clamp);
+        self.set_alpha(&alpha);
+    }
+
+    /// Scale colour by alpha, for tools that expect premultiplied data
+    pub fn premultiply_alpha(&mut self) {
+        self.image.par_map_inplace(|pixel| {
+            let [r, g, b, _] = premultiply(*pixel);
+            let c = |v: f64| v.round().clamp(0.0, 255.0) as u8;
+            *pixel = Rgba([c(r), c(g), c(b), pixel[3]]);
+        });
+    }
+
+    /// Inverse of [`Image::premultiply_alpha`], fully transparent pixels
+    /// become transparent black
+    pub fn unpremultiply_alpha(&mut self) {
+        self.image.par_map_inplace(|pixel| {
+            let Rgba([r, g, b, a]) = *pixel;
+            *pixel = unpremultiply([r as f64, g as f64, b as f64, a as f64]);
+        });
+    }
+}
@@ -0,0 +1,365 @@
+use std::f64::consts::{ FRAC_PI_2, TAU };
+
+use image::Rgba;
+use ndarray::Zip;
+
+use super::Image;
+use super::raster::{ self, Contour, FillRule, Stroke };
+use super::transform::{ Affine, apply };
+
+#[derive(Clone, Copy, Debug)]
+pub enum Segment {
+    MoveTo([f64; 2]),
+    LineTo([f64; 2]),
+    QuadTo([f64; 2], [f64; 2]),
+    CubicTo([f64; 2], [f64; 2], [f64; 2]),
+    Close,
+}
+
+/// Vector outline made of sub paths, arcs are stored as cubics so every
+/// segment stays exact under affine transforms
+#[derive(Clone, Debug, Default)]
+pub struct Path {
+    pub segments: Vec<Segment>,
+    /// End of the last segment
+    current: [f64; 2],
+    /// Start of the current sub path, where `Close` returns to
+    start: [f64; 2],
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn current(&self) -> [f64; 2] {
+        self.current
+    }
+
+    fn push(&mut self, segment: Segment) {
+        match segment {
+            Segment::MoveTo(p) => (self.current, self.start) = (p, p),
+            Segment::LineTo(p) | Segment::QuadTo(_, p) | Segment::CubicTo(_, _, p) => self.current = p,
+            Segment::Close => self.current = self.start,
+        }
+        self.segments.push(segment);
+    }
+
+    pub fn move_to(&mut self, p: [f64; 2]) {
+        self.push(Segment::MoveTo(p));
+    }
+
+    pub fn line_to(&mut self, p: [f64; 2]) {
+        self.push(Segment::LineTo(p));
+    }
+
+    pub fn quad_to(&mut self, c: [f64; 2], p: [f64; 2]) {
+        self.push(Segment::QuadTo(c, p));
+    }
+
+    pub fn cubic_to(&mut self, c1: [f64; 2], c2: [f64; 2], p: [f64; 2]) {
+        self.push(Segment::CubicTo(c1, c2, p));
+    }
+
+    pub fn close(&mut self) {
+        self.push(Segment::Close);
+    }
+
+    /// SVG style elliptical arc from the current point to `p`, `rotation` in
+    /// degrees. Converted to cubics of at most a quarter turn each.
+    pub fn arc_to(&mut self, radii: [f64; 2], rotation: f64, large: bool, sweep: bool, p: [f64; 2]) {
+        let p0 = self.current();
+        let (mut rx, mut ry) = (radii[0].abs(), radii[1].abs());
+        if rx < 1e-9 || ry < 1e-9 || (p0[0] - p[0]).hypot(p0[1] - p[1]) < 1e-9 {
+            self.line_to(p);
+            return;
+        }
+
+        // endpoint to center parameterisation, SVG 1.1 appendix F.6.5
+        let (sin, cos) = rotation.to_radians().sin_cos();
+        let (hx, hy) = ((p0[0] - p[0]) / 2.0, (p0[1] - p[1]) / 2.0);
+        let x1 = cos * hx + sin * hy;
+        let y1 = -sin * hx + cos * hy;
+
+        let lambda = (x1 / rx).powi(2) + (y1 / ry).powi(2);
+        if lambda > 1.0 {
+            // radii too small to reach, scale them up just enough
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+        let num = (rx * ry).powi(2) - (rx * y1).powi(2) - (ry * x1).powi(2);
+        let den = (rx * y1).powi(2) + (ry * x1).powi(2);
+        let mut k = (num / den).max(0.0).sqrt();
+        if large == sweep {
+            k = -k;
+        }
+        let (cx1, cy1) = (k * rx * y1 / ry, -k * ry * x1 / rx);
+        let cx = cos * cx1 - sin * cy1 + (p0[0] + p[0]) / 2.0;
+        let cy = sin * cx1 + cos * cy1 + (p0[1] + p[1]) / 2.0;
+
+        let angle = |ux: f64, uy: f64| uy.atan2(ux);
+        let theta = angle((x1 - cx1) / rx, (y1 - cy1) / ry);
+        let mut delta = angle((-x1 - cx1) / rx, (-y1 - cy1) / ry) - theta;
+        if sweep && delta < 0.0 {
+            delta += TAU;
+        } else if !sweep && delta > 0.0 {
+            delta -= TAU;
+        }
+
+        let n = (delta.abs() / FRAC_PI_2 - 1e-9).ceil().max(1.0) as usize;
+        let step = delta / n as f64;
+        let k = 4.0 / 3.0 * (step / 4.0).tan();
+        let point = |t: f64| {
+            let (s, c) = t.sin_cos();
+            let (x, y) = (rx * c, ry * s);
+            [cx + cos * x - sin * y, cy + sin * x + cos * y]
+        };
+        let tangent = |t: f64| {
+            let (s, c) = t.sin_cos();
+            let (x, y) = (-rx * s, ry * c);
+            [cos * x - sin * y, sin * x + cos * y]
+        };
+
+        for i in 0..n {
+            let (t0, t1) = (theta + step * i as f64, theta + step * (i + 1) as f64);
+            let (a, b) = (point(t0), point(t1));
+            let (da, db) = (tangent(t0), tangent(t1));
+            let end = if i + 1 == n { p } else { b };
+            self.cubic_to(
+                [a[0] + k * da[0], a[1] + k * da[1]],
+                [b[0] - k * db[0], b[1] - k * db[1]],
+                end,
+            );
+        }
+    }
+
+    /// Parse SVG path data, the `d` attribute of a `<path>` element
+    pub fn from_svg(d: &str) -> Result<Self, String> {
+        let mut scanner = Scanner { chars: d.as_bytes(), pos: 0 };
+        let mut path = Path::new();
+        let mut command = None;
+        // reflected control point for the smooth curve commands
+        let mut last_control: Option<(u8, [f64; 2])> = None;
+
+        loop {
+            scanner.skip_separators();
+            let Some(c) = scanner.peek() else { break };
+            if c.is_ascii_alphabetic() {
+                scanner.pos += 1;
+                command = Some(c);
+            } else if command.is_none() {
+                return Err(format!("expected a path command, found '{}'", c as char));
+            }
+            let cmd = command.unwrap();
+            let relative = cmd.is_ascii_lowercase();
+            let current = path.current();
+            let rel = |p: [f64; 2]| if relative { [p[0] + current[0], p[1] + current[1]] } else { p };
+            let reflect = |kind: &[u8]| match last_control {
+                Some((k, c)) if kind.contains(&k) => [2.0 * current[0] - c[0], 2.0 * current[1] - c[1]],
+                _ => current,
+            };
+
+            let mut control = None;
+            match cmd.to_ascii_uppercase() {
+                b'M' => {
+                    path.move_to(rel(scanner.point()?));
+                    // further pairs are implicit line-tos
+                    command = Some(if relative { b'l' } else { b'L' });
+                }
+                b'L' => path.line_to(rel(scanner.point()?)),
+                b'H' => {
+                    let x = scanner.number()?;
+                    path.line_to([if relative { current[0] + x } else { x }, current[1]]);
+                }
+                b'V' => {
+                    let y = scanner.number()?;
+                    path.line_to([current[0], if relative { current[1] + y } else { y }]);
+                }
+                b'C' => {
+                    let (c1, c2, p) = (rel(scanner.point()?), rel(scanner.point()?), rel(scanner.point()?));
+                    path.cubic_to(c1, c2, p);
+                    control = Some((b'C', c2));
+                }
+                b'S' => {
+                    let c1 = reflect(b"C");
+                    let (c2, p) = (rel(scanner.point()?), rel(scanner.point()?));
+                    path.cubic_to(c1, c2, p);
+                    control = Some((b'C', c2));
+                }
+                b'Q' => {
+                    let (c, p) = (rel(scanner.point()?), rel(scanner.point()?));
+                    path.quad_to(c, p);
+                    control = Some((b'Q', c));
+                }
+                b'T' => {
+                    let c = reflect(b"Q");
+                    path.quad_to(c, rel(scanner.point()?));
+                    control = Some((b'Q', c));
+                }
+                b'A' => {
+                    let radii = [scanner.number()?, scanner.number()?];
+                    let rotation = scanner.number()?;
+                    let large = scanner.flag()?;
+                    let sweep = scanner.flag()?;
+                    path.arc_to(radii, rotation, large, sweep, rel(scanner.point()?));
+                }
+                b'Z' => {
+                    path.close();
+                    command = None;
+                }
+                _ => return Err(format!("unknown path command '{}'", cmd as char)),
+            }
+            last_control = control;
+        }
+        Ok(path)
+    }
+
+    pub fn transform(&mut self, m: &Affine) {
+        let map = |p: [f64; 2]| {
+            let (x, y) = apply(m, p[0], p[1]);
+            [x, y]
+        };
+        self.current = map(self.current);
+        self.start = map(self.start);
+        for segment in self.segments.iter_mut() {
+            *segment = match *segment {
+                Segment::MoveTo(p) => Segment::MoveTo(map(p)),
+                Segment::LineTo(p) => Segment::LineTo(map(p)),
+                Segment::QuadTo(c, p) => Segment::QuadTo(map(c), map(p)),
+                Segment::CubicTo(c1, c2, p) => Segment::CubicTo(map(c1), map(c2), map(p)),
+                Segment::Close => Segment::Close,
+            };
+        }
+    }
+
+    /// Sub paths flattened to polygons, each with whether it was closed
+    pub fn flatten(&self) -> Vec<(Contour, bool)> {
+        let mut out = Vec::new();
+        let mut contour: Contour = Vec::new();
+        let mut start = [0.0, 0.0];
+
+        for segment in &self.segments {
+            let current = contour.last().copied().unwrap_or(start);
+            if contour.is_empty() && !matches!(segment, Segment::MoveTo(_) | Segment::Close) {
+                contour.push(current);
+            }
+            match *segment {
+                Segment::MoveTo(p) => {
+                    if contour.len() > 1 {
+                        out.push((std::mem::take(&mut contour), false));
+                    }
+                    contour = vec![p];
+                    start = p;
+                }
+                Segment::LineTo(p) => contour.push(p),
+                Segment::QuadTo(c, p) => raster::quadratic(&mut contour, current, c, p),
+                Segment::CubicTo(c1, c2, p) => raster::cubic(&mut contour, current, c1, c2, p),
+                Segment::Close => {
+                    if !contour.is_empty() {
+                        out.push((std::mem::take(&mut contour), true));
+                    }
+                }
+            }
+        }
+        if contour.len() > 1 {
+            out.push((contour, false));
+        }
+        out
+    }
+
+    /// `[x y w h]` of the flattened outline, `None` for an empty path
+    pub fn bbox(&self) -> Option<[f64; 4]> {
+        let contours = self.flatten();
+        let mut points = contours.iter().flat_map(|(c, _)| c.iter());
+        let first = *points.next()?;
+        let [x0, y0, x1, y1] = points.fold([first[0], first[1], first[0], first[1]], |b, p| {
+            [b[0].min(p[0]), b[1].min(p[1]), b[2].max(p[0]), b[3].max(p[1])]
+        });
+        Some([x0, y0, x1 - x0, y1 - y0])
+    }
+}
+
+struct Scanner<'a> {
+    chars: &'a [u8],
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_separators(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_ascii_whitespace() || c == b',') {
+            self.pos += 1;
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        self.skip_separators();
+        let start = self.pos;
+        if matches!(self.peek(), Some(b'+' | b'-')) {
+            self.pos += 1;
+        }
+        let mut dot = false;
+        while let Some(c) = self.peek() {
+            match c {
+                b'0'..=b'9' => self.pos += 1,
+                // a second dot starts the next number, as in "0.5.5"
+                b'.' if !dot => {
+                    dot = true;
+                    self.pos += 1;
+                }
+                b'e' | b'E' => {
+                    self.pos += 1;
+                    if matches!(self.peek(), Some(b'+' | b'-')) {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+        let text = std::str::from_utf8(&self.chars[start..self.pos]).unwrap_or_default();
+        text.parse().map_err(|_| format!("expected a number at offset {}", start))
+    }
+
+    fn point(&mut self) -> Result<[f64; 2], String> {
+        Ok([self.number()?, self.number()?])
+    }
+
+    /// Arc flags are single digits that may be written without separators
+    fn flag(&mut self) -> Result<bool, String> {
+        self.skip_separators();
+        match self.peek() {
+            Some(b'0') => { self.pos += 1; Ok(false) }
+            Some(b'1') => { self.pos += 1; Ok(true) }
+            _ => Err(format!("expected an arc flag at offset {}", self.pos)),
+        }
+    }
+}
+
+impl Image {
+    pub fn fill_path(&mut self, path: &Path, color: Rgba<u8>, rule: FillRule) {
+        let contours = path.flatten().into_iter().map(|(c, _)| c).collect::<Vec<_>>();
+        self.fill_contours(&contours, color, rule);
+    }
+
+    pub fn stroke_path(&mut self, path: &Path, color: Rgba<u8>, style: &Stroke) {
+        let pieces = path.flatten()
+            .into_iter()
+            .flat_map(|(c, closed)| raster::stroke(&c, closed, style))
+            .collect::<Vec<_>>();
+        self.fill_contours(&pieces, color, FillRule::NonZero);
+    }
+
+    /// Keep only what is inside the path, alpha is scaled by the coverage
+    pub fn clip_path(&mut self, path: &Path, rule: FillRule) {
+        let contours = path.flatten().into_iter().map(|(c, _)| c).collect::<Vec<_>>();
+        let coverage = raster::coverage(&contours, self.width, self.height, rule);
+        Zip::from(&mut self.image)
+            .and(&coverage)
+            .par_for_each(|pixel, &c| {
+                pixel[3] = (pixel[3] as f32 * c).round() as u8;
+            });
+    }
+}
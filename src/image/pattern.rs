@@ -0,0 +1,80 @@
+use std::f64::consts::PI;
+
+use ndarray::{
+    Zip,
+    prelude::*,
+};
+
+use super::{ EdgeMode, Image, premultiply, unpremultiply };
+use super::transform::Filter;
+
+/// Weight of the original image at `i` along an axis of `len` when making
+/// it seamless, easing from 0 at the edges to 1 at `band` pixels in
+#[inline(always)]
+fn seam_weight(i: usize, len: usize, band: f64) -> f64 {
+    let d = i.min(len - 1 - i) as f64;
+    let t = (d / band).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl Image {
+    /// Repeat the image over a `width` by `height` area. `mirror` flips every
+    /// other tile so edges meet their own reflection, `offset` shifts each
+    /// row of tiles by that fraction of the tile width, 0.5 gives bricks.
+    pub fn tile(&mut self, width: usize, height: usize, mirror: bool, offset: f64) {
+        let (tile_width, tile_height) = (self.width as f64, self.height as f64);
+        let edge = if mirror { EdgeMode::Mirror } else { EdgeMode::Wrap };
+        self.warp(width, height, |x, y| {
+            let row = (y / tile_height).floor();
+            Some((x - (row * offset * tile_width).round(), y))
+        }, Filter::Nearest, edge);
+    }
+
+    /// Cross-fade the borders with a copy shifted by half the image so the
+    /// result tiles without visible seams. `band` is the fraction of the
+    /// width and height blended on each side, at most a half.
+    pub fn seamless(&mut self, band: f64) {
+        let band = band.clamp(0.0, 0.5);
+        for axis in [Axis(1), Axis(0)] {
+            let len = self.image.len_of(axis);
+            let band = (band * len as f64).max(1.0);
+            if len < 2 {
+                continue;
+            }
+
+            let mut shifted = self.image.clone();
+            let half = len / 2;
+            for (i, mut lane) in shifted.axis_iter_mut(axis).enumerate() {
+                lane.assign(&self.image.index_axis(axis, (i + half) % len));
+            }
+
+            Zip::indexed(&mut self.image)
+                .and(&shifted)
+                .par_for_each(|(y, x), pixel, &other| {
+                    let i = if axis == Axis(1) { x } else { y };
+                    let w = seam_weight(i, len, band);
+                    let (a, b) = (premultiply(*pixel), premultiply(other));
+                    *pixel = unpremultiply([0, 1, 2, 3].map(|c| a[c] * w + b[c] * (1.0 - w)));
+                });
+        }
+    }
+
+    /// Fold the image into `segments` mirrored pairs of wedges around
+    /// `(cx, cy)`, starting at `degrees` clockwise from the x axis. A single
+    /// segment mirrors the image across the line through the center.
+    pub fn kaleidoscope(&mut self, segments: usize, degrees: f64, cx: f64, cy: f64, filter: Filter, edge: EdgeMode) {
+        let wedge = PI / segments.max(1) as f64;
+        let start = degrees.to_radians();
+        let (cx, cy) = (cx - 0.5, cy - 0.5);
+        self.warp(self.width, self.height, |x, y| {
+            let (dx, dy) = (x - cx, y - cy);
+            let r = dx.hypot(dy);
+            let mut theta = (dy.atan2(dx) - start).rem_euclid(2.0 * wedge);
+            if theta > wedge {
+                theta = 2.0 * wedge - theta;
+            }
+            let (sin, cos) = (theta + start).sin_cos();
+            Some((cx + r * cos, cy + r * sin))
+        }, filter, edge);
+    }
+}
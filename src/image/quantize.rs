@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use image::Rgba;
+use ndarray::{
+    parallel::prelude::*,
+    prelude::*,
+};
+
+use super::Image;
+
+#[derive(Clone, Copy, Debug)]
+pub enum PaletteMethod {
+    MedianCut,
+    KMeans,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Dither {
+    None,
+    FloydSteinberg,
+    Atkinson,
+    JarvisJudiceNinke,
+    /// Ordered dithering with a Bayer matrix of the given size (2, 4, 8 or 16)
+    Bayer(usize),
+    BlueNoise,
+}
+
+type Lab = [f32; 3];
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+/// sRGB (`0..=255`) to OKLab, coefficients as published by Björn Ottosson
+#[allow(clippy::excessive_precision)]
+pub fn to_oklab(pixel: Rgba<u8>) -> Lab {
+    let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|c| srgb_to_linear(c as f32 / 255.0));
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+/// OKLab to opaque sRGB
+#[allow(clippy::excessive_precision)]
+pub fn from_oklab(lab: Lab) -> Rgba<u8> {
+    let [l, a, b] = lab;
+    let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    let rgb = [
+        4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_,
+        -1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_,
+        -0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_,
+    ].map(|c| (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0).round() as u8);
+    Rgba([rgb[0], rgb[1], rgb[2], 255])
+}
+
+#[inline(always)]
+fn distance2(a: Lab, b: Lab) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+#[inline(always)]
+fn nearest(palette: &[Lab], color: Lab) -> usize {
+    let mut best = (0, f32::INFINITY);
+    for (i, &p) in palette.iter().enumerate() {
+        let d = distance2(p, color);
+        if d < best.1 {
+            best = (i, d);
+        }
+    }
+    best.0
+}
+
+/// Unique colours (ignoring fully transparent pixels) with their counts
+fn histogram(image: &Image) -> Vec<(Lab, f32)> {
+    let mut counts = HashMap::<[u8; 3], u32>::new();
+    for p in image.image.iter().filter(|p| p[3] > 0) {
+        *counts.entry([p[0], p[1], p[2]]).or_default() += 1;
+    }
+    counts.into_iter()
+        .map(|(c, n)| (to_oklab(Rgba([c[0], c[1], c[2], 255])), n as f32))
+        .collect()
+}
+
+fn weighted_mean(colors: &[(Lab, f32)]) -> Lab {
+    let mut acc = [0.0; 3];
+    let mut total = 0.0;
+    for (c, w) in colors {
+        for i in 0..3 {
+            acc[i] += c[i] * w;
+        }
+        total += w;
+    }
+    acc.map(|v| v / total.max(f32::EPSILON))
+}
+
+/// Repeatedly split the box with the widest spread at its weighted median
+fn median_cut(mut colors: Vec<(Lab, f32)>, n: usize) -> Vec<Lab> {
+    let mut boxes = vec![colors.as_mut_slice()];
+    while boxes.len() < n {
+        // widest axis over all splittable boxes
+        let widest = boxes.iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let (axis, range) = (0..3)
+                    .map(|a| {
+                        let lo = b.iter().map(|c| c.0[a]).fold(f32::INFINITY, f32::min);
+                        let hi = b.iter().map(|c| c.0[a]).fold(f32::NEG_INFINITY, f32::max);
+                        (a, hi - lo)
+                    })
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .unwrap();
+                (i, axis, range)
+            })
+            .max_by(|a, b| a.2.total_cmp(&b.2));
+        let Some((i, axis, _)) = widest else { break };
+
+        let b = boxes.swap_remove(i);
+        b.sort_unstable_by(|x, y| x.0[axis].total_cmp(&y.0[axis]));
+        let half = b.iter().map(|c| c.1).sum::<f32>() / 2.0;
+        let mut seen = 0.0;
+        let mut split = 1;
+        for (j, c) in b.iter().enumerate() {
+            seen += c.1;
+            if seen >= half {
+                split = (j + 1).clamp(1, b.len() - 1);
+                break;
+            }
+        }
+        let (lo, hi) = b.split_at_mut(split);
+        boxes.push(lo);
+        boxes.push(hi);
+    }
+    boxes.iter().map(|b| weighted_mean(b)).collect()
+}
+
+/// Lloyd's k-means seeded by median cut
+fn kmeans(colors: Vec<(Lab, f32)>, n: usize) -> Vec<Lab> {
+    const ITERATIONS: usize = 16;
+    let mut centers = median_cut(colors.clone(), n);
+
+    for _ in 0..ITERATIONS {
+        let mut sums = vec![([0.0f32; 3], 0.0f32); centers.len()];
+        for (c, w) in &colors {
+            let (acc, total) = &mut sums[nearest(&centers, *c)];
+            for i in 0..3 {
+                acc[i] += c[i] * w;
+            }
+            *total += w;
+        }
+
+        let mut moved = 0.0f32;
+        for (center, (acc, total)) in centers.iter_mut().zip(sums) {
+            if total > 0.0 {
+                let next = acc.map(|v| v / total);
+                moved = moved.max(distance2(*center, next));
+                *center = next;
+            }
+        }
+        if moved < 1e-8 {
+            break;
+        }
+    }
+    centers
+}
+
+fn bayer_matrix(size: usize) -> Array2<f32> {
+    let mut m = array![[0.0f32]];
+    while m.nrows() < size {
+        let n = m.nrows();
+        let mut next = Array2::<f32>::zeros((2 * n, 2 * n));
+        for ((y, x), &v) in m.indexed_iter() {
+            next[[y, x]] = 4.0 * v;
+            next[[y, x + n]] = 4.0 * v + 2.0;
+            next[[y + n, x]] = 4.0 * v + 3.0;
+            next[[y + n, x + n]] = 4.0 * v + 1.0;
+        }
+        m = next;
+    }
+    let cells = (size * size) as f32;
+    m.mapv(|v| (v + 0.5) / cells)
+}
+
+/// 64x64 blue noise threshold map made with the void-and-cluster method,
+/// generated once on first use
+fn blue_noise() -> &'static Array2<f32> {
+    static NOISE: OnceLock<Array2<f32>> = OnceLock::new();
+    NOISE.get_or_init(|| {
+        const SIZE: usize = 64;
+        const SIGMA: f32 = 1.5;
+        let n = SIZE * SIZE;
+
+        // toroidal Gaussian energy contribution of a point at offset (dx, dy)
+        let falloff = Array2::from_shape_fn((SIZE, SIZE), |(dy, dx)| {
+            let dx = dx.min(SIZE - dx) as f32;
+            let dy = dy.min(SIZE - dy) as f32;
+            (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+        });
+        let mut energy = vec![0.0f32; n];
+        let mut on = vec![false; n];
+        let toggle = |energy: &mut [f32], on: &mut [bool], i: usize, set: bool| {
+            on[i] = set;
+            let sign = if set { 1.0 } else { -1.0 };
+            let (iy, ix) = (i / SIZE, i % SIZE);
+            for (j, e) in energy.iter_mut().enumerate() {
+                let (jy, jx) = (j / SIZE, j % SIZE);
+                *e += sign * falloff[[(jy + SIZE - iy) % SIZE, (jx + SIZE - ix) % SIZE]];
+            }
+        };
+        let tightest = |energy: &[f32], on: &[bool], want: bool| {
+            (0..n)
+                .filter(|&i| on[i] == want)
+                .max_by(|&a, &b| {
+                    let (ea, eb) = (energy[a], energy[b]);
+                    if want { ea.total_cmp(&eb) } else { eb.total_cmp(&ea) }
+                })
+                .unwrap()
+        };
+
+        // deterministic initial pattern of ~10% points
+        let mut state = 0x2545F4914F6CDD1Du64;
+        let initial = n / 10;
+        while on.iter().filter(|&&v| v).count() < initial {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let i = (state % n as u64) as usize;
+            if !on[i] {
+                toggle(&mut energy, &mut on, i, true);
+            }
+        }
+
+        // move points from the tightest cluster to the largest void until
+        // stable, capped in case swaps keep cycling between equal energies
+        for _ in 0..n {
+            let cluster = tightest(&energy, &on, true);
+            toggle(&mut energy, &mut on, cluster, false);
+            let void = tightest(&energy, &on, false);
+            if void == cluster {
+                toggle(&mut energy, &mut on, cluster, true);
+                break;
+            }
+            toggle(&mut energy, &mut on, void, true);
+        }
+
+        let mut rank = vec![0usize; n];
+        let prototype = (on.clone(), energy.clone());
+
+        // phase 1: rank the initial points by removing clusters
+        let mut ones = initial;
+        while ones > 0 {
+            let cluster = tightest(&energy, &on, true);
+            toggle(&mut energy, &mut on, cluster, false);
+            ones -= 1;
+            rank[cluster] = ones;
+        }
+
+        // phase 2 and 3: fill voids until every cell is ranked
+        let (mut on, mut energy) = prototype;
+        for r in initial..n {
+            let void = tightest(&energy, &on, false);
+            toggle(&mut energy, &mut on, void, true);
+            rank[void] = r;
+        }
+
+        Array2::from_shape_fn((SIZE, SIZE), |(y, x)| (rank[y * SIZE + x] as f32 + 0.5) / n as f32)
+    })
+}
+
+impl Image {
+    /// Pick up to `n` representative colours, computed in OKLab
+    pub fn build_palette(&self, n: usize, method: PaletteMethod) -> Vec<Rgba<u8>> {
+        let colors = histogram(self);
+        if colors.is_empty() || n == 0 {
+            return vec![];
+        }
+        if colors.len() <= n {
+            return colors.iter().map(|c| from_oklab(c.0)).collect();
+        }
+        let centers = match method {
+            PaletteMethod::MedianCut => median_cut(colors, n),
+            PaletteMethod::KMeans => kmeans(colors, n),
+        };
+        centers.into_iter().map(from_oklab).collect()
+    }
+
+    /// Map every pixel to its nearest palette colour (in OKLab), alpha is
+    /// kept as is
+    pub fn quantize(&mut self, palette: &[Rgba<u8>], dither: Dither) {
+        if palette.is_empty() {
+            return;
+        }
+        let lab_palette = palette.iter().map(|&p| to_oklab(p)).collect::<Vec<_>>();
+        let opaque = palette.iter().map(|p| [p[0], p[1], p[2]]).collect::<Vec<_>>();
+        let set = |pixel: &mut Rgba<u8>, i: usize| {
+            let [r, g, b] = opaque[i];
+            *pixel = Rgba([r, g, b, pixel[3]]);
+        };
+
+        let diffusion: &[(isize, isize, f32)] = match dither {
+            // (dx, dy, weight)
+            Dither::FloydSteinberg => &[
+                (1, 0, 7.0 / 16.0),
+                (-1, 1, 3.0 / 16.0), (0, 1, 5.0 / 16.0), (1, 1, 1.0 / 16.0),
+            ],
+            Dither::Atkinson => &[
+                (1, 0, 1.0 / 8.0), (2, 0, 1.0 / 8.0),
+                (-1, 1, 1.0 / 8.0), (0, 1, 1.0 / 8.0), (1, 1, 1.0 / 8.0),
+                (0, 2, 1.0 / 8.0),
+            ],
+            Dither::JarvisJudiceNinke => &[
+                (1, 0, 7.0 / 48.0), (2, 0, 5.0 / 48.0),
+                (-2, 1, 3.0 / 48.0), (-1, 1, 5.0 / 48.0), (0, 1, 7.0 / 48.0), (1, 1, 5.0 / 48.0), (2, 1, 3.0 / 48.0),
+                (-2, 2, 1.0 / 48.0), (-1, 2, 3.0 / 48.0), (0, 2, 5.0 / 48.0), (1, 2, 3.0 / 48.0), (2, 2, 1.0 / 48.0),
+            ],
+            _ => &[],
+        };
+
+        match dither {
+            Dither::None => {
+                self.image.par_map_inplace(|pixel| {
+                    set(pixel, nearest(&lab_palette, to_oklab(*pixel)));
+                });
+            }
+
+            Dither::Bayer(_) | Dither::BlueNoise => {
+                let bayer;
+                let matrix = match dither {
+                    Dither::Bayer(size) => {
+                        bayer = bayer_matrix(size);
+                        &bayer
+                    }
+                    _ => blue_noise(),
+                };
+                let (mh, mw) = matrix.dim();
+
+                // scale the threshold offset to the typical palette spacing
+                let spread = if lab_palette.len() > 1 {
+                    lab_palette.iter()
+                        .map(|&a| {
+                            lab_palette.iter()
+                                .filter(|&&b| b != a)
+                                .map(|&b| distance2(a, b))
+                                .fold(f32::INFINITY, f32::min)
+                                .sqrt()
+                        })
+                        .sum::<f32>() / lab_palette.len() as f32
+                } else {
+                    0.0
+                };
+
+                self.image.axis_iter_mut(Axis(0))
+                    .into_par_iter()
+                    .enumerate()
+                    .for_each(|(y, mut row)| {
+                        for (x, pixel) in row.iter_mut().enumerate() {
+                            let offset = (matrix[[y % mh, x % mw]] - 0.5) * spread;
+                            let lab = to_oklab(*pixel);
+                            let lab = [lab[0] + offset, lab[1], lab[2]];
+                            set(pixel, nearest(&lab_palette, lab));
+                        }
+                    });
+            }
+
+            _ => {
+                // Error diffusion is inherently sequential, carried in OKLab
+                let mut lab = self.image.map(|&p| to_oklab(p));
+                let (height, width) = lab.dim();
+                for y in 0..height {
+                    for x in 0..width {
+                        let old = lab[[y, x]];
+                        let i = nearest(&lab_palette, old);
+                        set(&mut self.image[[y, x]], i);
+                        if self.image[[y, x]][3] == 0 {
+                            continue;
+                        }
+                        let error = [0, 1, 2].map(|c| old[c] - lab_palette[i][c]);
+                        for &(dx, dy, w) in diffusion {
+                            let (nx, ny) = (x as isize + dx, y + dy as usize);
+                            if nx < 0 || nx >= width as isize || ny >= height {
+                                continue;
+                            }
+                            let target = &mut lab[[ny, nx as usize]];
+                            for c in 0..3 {
+                                target[c] += error[c] * w;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Save as a paletted PNG or GIF (picked from the extension). The image
+    /// must contain at most 256 distinct colours, see `quantize`.
+    pub fn to_indexed_file(&self, path: &str) -> Result<(), String> {
+        let mut palette = Vec::<[u8; 4]>::new();
+        let mut lookup = HashMap::<[u8; 4], u8>::new();
+        let mut indices = Vec::with_capacity(self.width * self.height);
+        for pixel in self.image.iter() {
+            // all fully transparent pixels share one entry
+            let key = if pixel[3] == 0 { [0, 0, 0, 0] } else { pixel.0 };
+            let index = match lookup.get(&key) {
+                Some(&i) => i,
+                None => {
+                    if palette.len() == 256 {
+                        return Err("image has more than 256 colours, quantize it first".to_string());
+                    }
+                    palette.push(key);
+                    lookup.insert(key, (palette.len() - 1) as u8);
+                    (palette.len() - 1) as u8
+                }
+            };
+            indices.push(index);
+        }
+
+        let extension = std::path::Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+        let writer = std::io::BufWriter::new(file);
+
+        match extension.as_deref() {
+            Some("png") => {
+                let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+                encoder.set_color(png::ColorType::Indexed);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_palette(palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect::<Vec<_>>());
+                if palette.iter().any(|c| c[3] < 255) {
+                    encoder.set_trns(palette.iter().map(|c| c[3]).collect::<Vec<_>>());
+                }
+                let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+                writer.write_image_data(&indices).map_err(|e| e.to_string())
+            }
+            Some("gif") => {
+                if self.width > u16::MAX as usize || self.height > u16::MAX as usize {
+                    return Err("image is too large for a GIF".to_string());
+                }
+                // GIF only has binary transparency, use the first transparent entry
+                let flat = palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect::<Vec<_>>();
+                let mut encoder = gif::Encoder::new(writer, self.width as u16, self.height as u16, &flat)
+                    .map_err(|e| e.to_string())?;
+                let mut frame = gif::Frame {
+                    width: self.width as u16,
+                    height: self.height as u16,
+                    buffer: std::borrow::Cow::Borrowed(&indices),
+                    ..Default::default()
+                };
+                frame.transparent = palette.iter().position(|c| c[3] < 128).map(|i| i as u8);
+                encoder.write_frame(&frame).map_err(|e| e.to_string())
+            }
+            _ => Err("indexed images can only be saved as .png or .gif".to_string()),
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use std::f64::consts::{ PI, TAU };
+
+use image::Rgba;
+use ndarray::{
+    Zip,
+    prelude::*,
+};
+
+use super::Image;
+use super::blend::BlendMode;
+
+/// Maximum distance in pixels between a curve and its flattened polygon
+const TOLERANCE: f64 = 0.2;
+
+/// A closed polygon, the last point connects back to the first
+pub type Contour = Vec<[f64; 2]>;
+
+#[derive(Clone, Copy, Debug)]
+pub enum FillRule {
+    NonZero,
+    EvenOdd,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Stroke {
+    pub width: f64,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    /// Longest miter allowed, as a multiple of the stroke width, before
+    /// falling back to a bevel
+    pub miter_limit: f64,
+}
+
+/// Number of segments needed to keep an arc of `radius` over `angle`
+/// within [`TOLERANCE`]
+fn arc_segments(radius: f64, angle: f64) -> usize {
+    if radius <= TOLERANCE {
+        return 4;
+    }
+    let step = 2.0 * (1.0 - TOLERANCE / radius).acos();
+    ((angle.abs() / step).ceil() as usize).max(4)
+}
+
+/// Append points along an elliptic arc, excluding the starting point
+pub fn arc(out: &mut Contour, center: [f64; 2], radii: [f64; 2], start: f64, sweep: f64) {
+    let n = arc_segments(radii[0].max(radii[1]), sweep);
+    for i in 1..=n {
+        let t = start + sweep * i as f64 / n as f64;
+        out.push([center[0] + radii[0] * t.cos(), center[1] + radii[1] * t.sin()]);
+    }
+}
+
+/// Append a flattened quadratic Bézier, excluding `p0`
+pub fn quadratic(out: &mut Contour, p0: [f64; 2], p1: [f64; 2], p2: [f64; 2]) {
+    // the second difference bounds the distance to the chord
+    let dd = ((p0[0] - 2.0 * p1[0] + p2[0]).hypot(p0[1] - 2.0 * p1[1] + p2[1])) / 4.0;
+    let n = ((dd / TOLERANCE).sqrt().ceil() as usize).max(1);
+    for i in 1..=n {
+        let t = i as f64 / n as f64;
+        let mt = 1.0 - t;
+        out.push([0, 1].map(|c| mt * mt * p0[c] + 2.0 * mt * t * p1[c] + t * t * p2[c]));
+    }
+}
+
+/// Append a flattened cubic Bézier, excluding `p0`
+pub fn cubic(out: &mut Contour, p0: [f64; 2], p1: [f64; 2], p2: [f64; 2], p3: [f64; 2]) {
+    let dd = |a: [f64; 2], b: [f64; 2], c: [f64; 2]| {
+        (a[0] - 2.0 * b[0] + c[0]).hypot(a[1] - 2.0 * b[1] + c[1])
+    };
+    let dd = dd(p0, p1, p2).max(dd(p1, p2, p3)) * 3.0 / 4.0;
+    let n = ((dd / TOLERANCE).sqrt().ceil() as usize).max(1);
+    for i in 1..=n {
+        let t = i as f64 / n as f64;
+        let mt = 1.0 - t;
+        out.push([0, 1].map(|c| {
+            mt * mt * mt * p0[c] + 3.0 * mt * mt * t * p1[c] + 3.0 * mt * t * t * p2[c] + t * t * t * p3[c]
+        }));
+    }
+}
+
+pub fn rect(x: f64, y: f64, w: f64, h: f64) -> Contour {
+    vec![[x, y], [x + w, y], [x + w, y + h], [x, y + h]]
+}
+
+/// Rectangle with corners rounded by `radius`, clamped to half the
+/// shortest side
+pub fn rounded_rect(x: f64, y: f64, w: f64, h: f64, radius: f64) -> Contour {
+    let r = radius.min(w.abs() / 2.0).min(h.abs() / 2.0).max(0.0);
+    if r <= 0.0 {
+        return rect(x, y, w, h);
+    }
+    let mut out = vec![[x + r, y]];
+    out.push([x + w - r, y]);
+    arc(&mut out, [x + w - r, y + r], [r, r], -PI / 2.0, PI / 2.0);
+    out.push([x + w, y + h - r]);
+    arc(&mut out, [x + w - r, y + h - r], [r, r], 0.0, PI / 2.0);
+    out.push([x + r, y + h]);
+    arc(&mut out, [x + r, y + h - r], [r, r], PI / 2.0, PI / 2.0);
+    out.push([x, y + r]);
+    arc(&mut out, [x + r, y + r], [r, r], PI, PI / 2.0);
+    out
+}
+
+pub fn ellipse(cx: f64, cy: f64, rx: f64, ry: f64) -> Contour {
+    let mut out = vec![[cx + rx, cy]];
+    arc(&mut out, [cx, cy], [rx, ry], 0.0, TAU);
+    out.pop();
+    out
+}
+
+fn signed_area(contour: &[[f64; 2]]) -> f64 {
+    let n = contour.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (contour[i], contour[(i + 1) % n]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum::<f64>() / 2.0
+}
+
+/// Outline of a stroke as a set of overlapping pieces, all wound the same way
+/// so they merge when filled with [`FillRule::NonZero`]
+pub fn stroke(points: &[[f64; 2]], closed: bool, style: &Stroke) -> Vec<Contour> {
+    let hw = style.width / 2.0;
+    let mut points = points.to_vec();
+    points.dedup_by(|a, b| (a[0] - b[0]).hypot(a[1] - b[1]) < 1e-9);
+    if closed && points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+
+    let mut pieces = Vec::new();
+    if hw <= 0.0 || points.is_empty() {
+        return pieces;
+    }
+    if points.len() == 1 {
+        // a lone point only shows with caps that extend past it
+        let [x, y] = points[0];
+        match style.cap {
+            LineCap::Butt => {}
+            LineCap::Round => pieces.push(ellipse(x, y, hw, hw)),
+            LineCap::Square => pieces.push(rect(x - hw, y - hw, 2.0 * hw, 2.0 * hw)),
+        }
+        return pieces;
+    }
+
+    let n = points.len();
+    let segments = if closed { n } else { n - 1 };
+    let direction = |i: usize| {
+        let (a, b) = (points[i % n], points[(i + 1) % n]);
+        let len = (b[0] - a[0]).hypot(b[1] - a[1]);
+        [(b[0] - a[0]) / len, (b[1] - a[1]) / len]
+    };
+    let offset = |p: [f64; 2], v: [f64; 2], s: f64| [p[0] + v[0] * s, p[1] + v[1] * s];
+
+    for i in 0..segments {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        let d = direction(i);
+        let normal = [-d[1] * hw, d[0] * hw];
+        pieces.push(vec![offset(a, normal, 1.0), offset(b, normal, 1.0), offset(b, normal, -1.0), offset(a, normal, -1.0)]);
+    }
+
+    // joins between consecutive segments
+    let joins = if closed { 0..n } else { 1..n - 1 };
+    for i in joins {
+        let v = points[i];
+        let d0 = direction((i + n - 1) % n);
+        let d1 = direction(i);
+        let cross = d0[0] * d1[1] - d0[1] * d1[0];
+        if cross.abs() < 1e-9 && d0[0] * d1[0] + d0[1] * d1[1] > 0.0 {
+            continue;
+        }
+        // the outer side of the turn
+        let side = if cross > 0.0 { -1.0 } else { 1.0 };
+        let n0 = [-d0[1] * hw * side, d0[0] * hw * side];
+        let n1 = [-d1[1] * hw * side, d1[0] * hw * side];
+
+        match style.join {
+            LineJoin::Round => pieces.push(ellipse(v[0], v[1], hw, hw)),
+            LineJoin::Bevel => pieces.push(vec![v, offset(v, n0, 1.0), offset(v, n1, 1.0)]),
+            LineJoin::Miter => {
+                let mid = [n0[0] + n1[0], n0[1] + n1[1]];
+                let mid_len = mid[0].hypot(mid[1]);
+                // cosine of half the angle between the two offsets
+                let cos_half = mid_len / (2.0 * hw);
+                if cos_half < 1e-9 || 1.0 / cos_half > style.miter_limit {
+                    pieces.push(vec![v, offset(v, n0, 1.0), offset(v, n1, 1.0)]);
+                } else {
+                    let tip = [mid[0] / mid_len * hw / cos_half, mid[1] / mid_len * hw / cos_half];
+                    pieces.push(vec![v, offset(v, n0, 1.0), offset(v, tip, 1.0), offset(v, n1, 1.0)]);
+                }
+            }
+        }
+    }
+
+    if !closed {
+        let ends = [(points[0], direction(0), -1.0), (points[n - 1], direction(n - 2), 1.0)];
+        for (p, d, outward) in ends {
+            let normal = [-d[1] * hw, d[0] * hw];
+            let out = [d[0] * hw * outward, d[1] * hw * outward];
+            match style.cap {
+                LineCap::Butt => {}
+                LineCap::Round => pieces.push(ellipse(p[0], p[1], hw, hw)),
+                LineCap::Square => pieces.push(vec![
+                    offset(p, normal, 1.0),
+                    offset(offset(p, normal, 1.0), out, 1.0),
+                    offset(offset(p, normal, -1.0), out, 1.0),
+                    offset(p, normal, -1.0),
+                ]),
+            }
+        }
+    }
+
+    for piece in pieces.iter_mut() {
+        if signed_area(piece) < 0.0 {
+            piece.reverse();
+        }
+    }
+    pieces
+}
+
+/// Signed area accumulation buffer, one extra cell on the right of every
+/// row catches edges touching the right border
+struct Accumulator {
+    width: usize,
+    height: usize,
+    cells: Vec<f64>,
+}
+
+impl Accumulator {
+    fn new(width: usize, height: usize) -> Self {
+        Self { width, height, cells: vec![0.0; (width + 2) * height] }
+    }
+
+    /// Add a line, splitting it at the left and right borders so the parts
+    /// outside can be flattened onto them without changing the coverage
+    fn line(&mut self, p0: [f64; 2], p1: [f64; 2]) {
+        let w = self.width as f64;
+        let mut cuts = vec![0.0, 1.0];
+        for edge in [0.0, w] {
+            let t = (edge - p0[0]) / (p1[0] - p0[0]);
+            if t > 0.0 && t < 1.0 {
+                cuts.push(t);
+            }
+        }
+        cuts.sort_by(f64::total_cmp);
+        let at = |t: f64| [p0[0] + (p1[0] - p0[0]) * t, p0[1] + (p1[1] - p0[1]) * t];
+        for pair in cuts.windows(2) {
+            let (a, b) = (at(pair[0]), at(pair[1]));
+            self.clipped_line([a[0].clamp(0.0, w), a[1]], [b[0].clamp(0.0, w), b[1]]);
+        }
+    }
+
+    /// Exact area coverage of a line with both ends inside `0..=width`,
+    /// after the accumulation approach of font-rs
+    fn clipped_line(&mut self, p0: [f64; 2], p1: [f64; 2]) {
+        if (p0[1] - p1[1]).abs() < 1e-12 {
+            return;
+        }
+        let (dir, p0, p1) = if p0[1] < p1[1] { (1.0, p0, p1) } else { (-1.0, p1, p0) };
+        let dxdy = (p1[0] - p0[0]) / (p1[1] - p0[1]);
+        let stride = self.width + 2;
+
+        let mut x = p0[0];
+        if p0[1] < 0.0 {
+            x -= p0[1] * dxdy;
+        }
+        let y_start = p0[1].max(0.0) as usize;
+        let y_end = (p1[1].ceil().max(0.0) as usize).min(self.height);
+
+        for y in y_start..y_end {
+            let row = &mut self.cells[y * stride..(y + 1) * stride];
+            let dy = ((y + 1) as f64).min(p1[1]) - (y as f64).max(p0[1]);
+            let x_next = x + dxdy * dy;
+            let d = dy * dir;
+            let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let x0_floor = x0.floor();
+            let x0i = x0_floor as usize;
+            let x1_ceil = x1.ceil();
+            let x1i = x1_ceil as usize;
+
+            if x1i <= x0i + 1 {
+                // the line stays within one pixel column
+                let xmf = 0.5 * (x + x_next) - x0_floor;
+                row[x0i] += d - d * xmf;
+                row[x0i + 1] += d * xmf;
+            } else {
+                let s = 1.0 / (x1 - x0);
+                let x0f = x0 - x0_floor;
+                let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+                let x1f = x1 - x1_ceil + 1.0;
+                let am = 0.5 * s * x1f * x1f;
+                row[x0i] += d * a0;
+                if x1i == x0i + 2 {
+                    row[x0i + 1] += d * (1.0 - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - x0f);
+                    row[x0i + 1] += d * (a1 - a0);
+                    for cell in row.iter_mut().take(x1i - 1).skip(x0i + 2) {
+                        *cell += d * s;
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f64 * s;
+                    row[x1i - 1] += d * (1.0 - a2 - am);
+                }
+                row[x1i] += d * am;
+            }
+            x = x_next;
+        }
+    }
+}
+
+/// Anti-aliased coverage in `0..=1` of the contours over a `width` by
+/// `height` grid
+pub fn coverage(contours: &[Contour], width: usize, height: usize, rule: FillRule) -> Array2<f32> {
+    let mut acc = Accumulator::new(width, height);
+    for contour in contours {
+        for (i, &p0) in contour.iter().enumerate() {
+            acc.line(p0, contour[(i + 1) % contour.len()]);
+        }
+    }
+
+    let stride = width + 2;
+    let mut output = Array2::<f32>::zeros((height, width));
+    for (y, mut out_row) in output.axis_iter_mut(Axis(0)).enumerate() {
+        let mut winding = 0.0;
+        for (x, out) in out_row.iter_mut().enumerate() {
+            winding += acc.cells[y * stride + x];
+            *out = match rule {
+                FillRule::NonZero => winding.abs().min(1.0),
+                FillRule::EvenOdd => {
+                    let w = winding.abs() % 2.0;
+                    if w > 1.0 { 2.0 - w } else { w }
+                }
+            } as f32;
+        }
+    }
+    output
+}
+
+impl Image {
+    /// Paint `color` over the image wherever `coverage` is non zero
+    pub fn fill_coverage(&mut self, coverage: &Array2<f32>, color: Rgba<u8>) {
+        Zip::from(&mut self.image)
+            .and(coverage)
+            .par_for_each(|pixel, &c| {
+                if c <= 0.0 {
+                    return;
+                }
+                let alpha = (color[3] as f32 * c.min(1.0)).round() as u8;
+                *pixel = BlendMode::Normal.blend_pixel(Rgba([color[0], color[1], color[2], alpha]), *pixel);
+            });
+    }
+
+    pub fn fill_contours(&mut self, contours: &[Contour], color: Rgba<u8>, rule: FillRule) {
+        let coverage = coverage(contours, self.width, self.height, rule);
+        self.fill_coverage(&coverage, color);
+    }
+}
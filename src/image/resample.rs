@@ -0,0 +1,231 @@
+use image::Rgba;
+use ndarray::{
+    parallel::prelude::*,
+    prelude::*,
+};
+
+use super::{ EdgeMode, Image, premultiply, unpremultiply };
+
+/// Reconstruction kernels for separable resizing
+#[derive(Clone, Copy, Debug)]
+pub enum Kernel {
+    /// Exact area averaging, each source pixel weighted by its overlap
+    Box,
+    /// Cubic with B = 0, C = 0.5, sharp with slight ringing
+    CatmullRom,
+    /// Cubic with B = C = 1/3, softer with almost no ringing
+    Mitchell,
+    Lanczos2,
+    Lanczos3,
+}
+
+/// Keys' family of cubics, see Mitchell and Netravali (1988)
+#[inline(always)]
+fn cubic(x: f64, b: f64, c: f64) -> f64 {
+    let x = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+            + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2)
+            + (6.0 - 2.0 * b)) / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x.powi(3)
+            + (6.0 * b + 30.0 * c) * x.powi(2)
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)) / 6.0
+    } else {
+        0.0
+    }
+}
+
+#[inline(always)]
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-8 {
+        1.0
+    } else {
+        let x = x * std::f64::consts::PI;
+        x.sin() / x
+    }
+}
+
+impl Kernel {
+    /// Radius of the kernel in source pixels when not minifying
+    fn support(&self) -> f64 {
+        match self {
+            Kernel::Box => 0.5,
+            Kernel::CatmullRom | Kernel::Mitchell | Kernel::Lanczos2 => 2.0,
+            Kernel::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(&self, x: f64) -> f64 {
+        match self {
+            Kernel::Box => if x.abs() <= 0.5 { 1.0 } else { 0.0 },
+            Kernel::CatmullRom => cubic(x, 0.0, 0.5),
+            Kernel::Mitchell => cubic(x, 1.0 / 3.0, 1.0 / 3.0),
+            Kernel::Lanczos2 => if x.abs() < 2.0 { sinc(x) * sinc(x / 2.0) } else { 0.0 },
+            Kernel::Lanczos3 => if x.abs() < 3.0 { sinc(x) * sinc(x / 3.0) } else { 0.0 },
+        }
+    }
+}
+
+/// Source taps of one output sample, as source indices and normalised weights
+type Taps = Vec<(Option<usize>, f32)>;
+
+/// Taps for every output position along an axis of `src_len` resized to
+/// `dst_len`. When minifying the kernel is stretched to cover the footprint
+/// of the output pixel so every source pixel contributes.
+fn axis_taps(src_len: usize, dst_len: usize, kernel: Kernel, edge: EdgeMode) -> Vec<Taps> {
+    let ratio = src_len as f64 / dst_len as f64;
+    let scale = ratio.max(1.0);
+    let support = kernel.support() * scale;
+
+    (0..dst_len)
+        .map(|i| {
+            // output pixel centers mapped onto the source grid
+            let center = (i as f64 + 0.5) * ratio - 0.5;
+            let start = (center - support).floor() as isize;
+            let end = (center + support).ceil() as isize;
+
+            let mut taps: Vec<(Option<usize>, f64)> = (start..=end)
+                .map(|j| {
+                    let w = match kernel {
+                        Kernel::Box => {
+                            // overlap of the source pixel with the output footprint,
+                            // which is narrower than a pixel when magnifying
+                            let lo = (j as f64 - 0.5).max(center - ratio / 2.0);
+                            let hi = (j as f64 + 0.5).min(center + ratio / 2.0);
+                            (hi - lo).max(0.0)
+                        }
+                        _ => kernel.weight((j as f64 - center) / scale),
+                    };
+                    (edge.index(j, src_len), w)
+                })
+                .filter(|(_, w)| *w != 0.0)
+                .collect();
+
+            let sum: f64 = taps.iter().map(|(_, w)| w).sum();
+            if sum.abs() > 1e-12 {
+                taps.iter_mut().for_each(|(_, w)| *w /= sum);
+            }
+            taps.into_iter().map(|(j, w)| (j, w as f32)).collect()
+        })
+        .collect()
+}
+
+/// Most taps a kernel centered on a point can touch along one axis
+const MAX_POINT_TAPS: usize = 7;
+
+/// Taps of a kernel centered on `center` along an axis of `len`, weights
+/// are normalised over all of them including those that fall off the edge
+fn point_taps(center: f64, len: usize, kernel: Kernel, edge: EdgeMode)
+    -> [(Option<usize>, f64); MAX_POINT_TAPS]
+{
+    let support = kernel.support();
+    let start = (center - support).ceil() as isize;
+    let mut taps = [(None, 0.0); MAX_POINT_TAPS];
+    let mut sum = 0.0;
+    for (k, tap) in taps.iter_mut().enumerate() {
+        let j = start + k as isize;
+        let w = kernel.weight(center - j as f64);
+        *tap = (edge.index(j, len), w);
+        sum += w;
+    }
+    if sum.abs() > 1e-12 {
+        taps.iter_mut().for_each(|(_, w)| *w /= sum);
+    }
+    taps
+}
+
+impl Image {
+    /// Sample at fractional coordinates where integer values are pixel
+    /// centers with the kernel centered on the point. Unlike a resize the
+    /// kernel is never stretched, so minifying transforms can alias.
+    pub fn sample_kernel(&self, x: f64, y: f64, kernel: Kernel, edge: EdgeMode) -> Rgba<u8> {
+        let x_taps = point_taps(x, self.width, kernel, edge);
+        let y_taps = point_taps(y, self.height, kernel, edge);
+
+        let mut acc = [0.0; 4];
+        for &(j, wy) in y_taps.iter().filter(|t| t.1 != 0.0) {
+            let Some(j) = j else { continue };
+            for &(i, wx) in x_taps.iter().filter(|t| t.1 != 0.0) {
+                let Some(i) = i else { continue };
+                let p = premultiply(self.image[[j, i]]);
+                for c in 0..4 {
+                    acc[c] += p[c] * wx * wy;
+                }
+            }
+        }
+        // negative lobes can push colour past its alpha
+        let alpha = acc[3].clamp(0.0, 255.0);
+        unpremultiply([acc[0].clamp(0.0, alpha), acc[1].clamp(0.0, alpha), acc[2].clamp(0.0, alpha), alpha])
+    }
+
+    /// Separable resize with the given kernel, done on premultiplied colour
+    pub fn resize_filtered(&mut self, new_width: usize, new_height: usize, kernel: Kernel, edge: EdgeMode) {
+        // nothing to sample from, the result is empty
+        if self.width == 0 || self.height == 0 {
+            *self = Image::new(new_width, new_height);
+            return;
+        }
+
+        let source = self.image.map(|&p| premultiply(p).map(|c| c as f32));
+        let x_taps = axis_taps(self.width, new_width, kernel, edge);
+        let y_taps = axis_taps(self.height, new_height, kernel, edge);
+
+        // Horizontal pass
+        let mut rows = Array2::from_elem((self.height, new_width), [0.0f32; 4]);
+        rows.axis_iter_mut(Axis(0))
+            .into_par_iter()
+            .zip(source.axis_iter(Axis(0)))
+            .for_each(|(mut out_row, in_row)| {
+                for (out, taps) in out_row.iter_mut().zip(&x_taps) {
+                    let mut acc = [0.0; 4];
+                    for &(j, w) in taps {
+                        let Some(j) = j else { continue };
+                        for c in 0..4 {
+                            acc[c] += in_row[j][c] * w;
+                        }
+                    }
+                    *out = acc;
+                }
+            });
+
+        // Vertical pass, whole rows at a time to stay cache friendly
+        let mut output = Array2::from_elem((new_height, new_width), [0.0f32; 4]);
+        output.axis_iter_mut(Axis(0))
+            .into_par_iter()
+            .zip(&y_taps)
+            .for_each(|(mut out_row, taps)| {
+                for &(j, w) in taps {
+                    let Some(j) = j else { continue };
+                    for (out, p) in out_row.iter_mut().zip(rows.row(j)) {
+                        for c in 0..4 {
+                            out[c] += p[c] * w;
+                        }
+                    }
+                }
+            });
+
+        self.image = output.map(|p| unpremultiply(p.map(|c| c as f64)));
+        self.width = new_width;
+        self.height = new_height;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty source resizes to a transparent image instead of panicking
+    #[test]
+    fn resize_empty_source() {
+        for kernel in [Kernel::Box, Kernel::CatmullRom, Kernel::Mitchell, Kernel::Lanczos2, Kernel::Lanczos3] {
+            for (width, height) in [(0, 0), (0, 3), (3, 0)] {
+                let mut image = Image::new(width, height);
+                image.resize_filtered(4, 4, kernel, EdgeMode::Clamp);
+                assert_eq!((image.width, image.height), (4, 4));
+                assert!(image.image.iter().all(|p| p[3] == 0));
+            }
+        }
+    }
+}
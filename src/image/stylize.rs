@@ -0,0 +1,192 @@
+use image::Rgba;
+use ndarray::{
+    Zip,
+    parallel::prelude::*,
+    prelude::*,
+};
+
+use super::{ EdgeMode, Image, premultiply, unpremultiply };
+
+#[derive(Clone, Copy, Debug)]
+pub enum Pixelate {
+    /// Average every pixel of the block
+    Average,
+    /// Take the pixel at the center of the block
+    Sample,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Screen {
+    Dot,
+    Line,
+}
+
+/// Split straight RGB in `0..=1` into CMYK inks in `0..=1`
+#[inline(always)]
+fn rgb_to_cmyk(rgb: [f64; 3]) -> [f64; 4] {
+    let k = 1.0 - rgb[0].max(rgb[1]).max(rgb[2]);
+    if k >= 1.0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    let [c, m, y] = rgb.map(|v| (1.0 - v - k) / (1.0 - k));
+    [c, m, y, k]
+}
+
+/// Threshold maximising the between-class variance of a 256 bin histogram
+fn otsu(hist: &[u64; 256]) -> f32 {
+    let total = hist.iter().sum::<u64>() as f64;
+    let weighted = hist.iter().enumerate().map(|(i, &n)| i as f64 * n as f64).sum::<f64>();
+
+    let (mut best, mut best_var) = (0, 0.0);
+    let (mut below, mut below_sum) = (0.0, 0.0);
+    for (t, &n) in hist.iter().enumerate() {
+        below += n as f64;
+        below_sum += t as f64 * n as f64;
+        let above = total - below;
+        if below == 0.0 || above == 0.0 {
+            continue;
+        }
+        let mean_below = below_sum / below;
+        let mean_above = (weighted - below_sum) / above;
+        let var = below * above * (mean_below - mean_above).powi(2);
+        if var > best_var {
+            best = t;
+            best_var = var;
+        }
+    }
+    // pixels above the threshold become white
+    best as f32 + 0.5
+}
+
+impl Image {
+    pub fn pixelate(&mut self, block: usize, mode: Pixelate) {
+        if block <= 1 {
+            return;
+        }
+        let (width, height) = (self.width, self.height);
+        let source = &self.image;
+
+        let mut output = Array2::from_elem((height, width), Rgba([0, 0, 0, 0]));
+        output.axis_chunks_iter_mut(Axis(0), block)
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(by, mut rows)| {
+                let y0 = by * block;
+                let y1 = (y0 + block).min(height);
+                for x0 in (0..width).step_by(block) {
+                    let x1 = (x0 + block).min(width);
+                    let color = match mode {
+                        Pixelate::Sample => source[[(y0 + y1) / 2, (x0 + x1) / 2]],
+                        Pixelate::Average => {
+                            let mut acc = [0.0; 4];
+                            for y in y0..y1 {
+                                for x in x0..x1 {
+                                    let p = premultiply(source[[y, x]]);
+                                    for c in 0..4 {
+                                        acc[c] += p[c];
+                                    }
+                                }
+                            }
+                            let n = ((x1 - x0) * (y1 - y0)) as f64;
+                            unpremultiply(acc.map(|c| c / n))
+                        }
+                    };
+                    rows.slice_mut(s![.., x0..x1]).fill(color);
+                }
+            });
+
+        self.image = output;
+    }
+
+    /// Quantise each channel to the given number of evenly spaced levels,
+    /// a level count below 2 leaves the channel untouched
+    pub fn posterize(&mut self, levels: [usize; 4]) {
+        self.image.par_map_inplace(|pixel| {
+            for (c, &n) in levels.iter().enumerate() {
+                if n < 2 {
+                    continue;
+                }
+                let steps = (n - 1) as f32;
+                let v = (pixel[c] as f32 / 255.0 * steps).round() / steps;
+                pixel[c] = (v * 255.0).round() as u8;
+            }
+        });
+    }
+
+    /// Black and white on luminance, alpha is kept. Without a threshold one
+    /// is picked with Otsu's method. Returns the threshold used.
+    pub fn threshold(&mut self, threshold: Option<f32>) -> f32 {
+        let luma = self.luma();
+        let threshold = threshold.unwrap_or_else(|| {
+            let mut hist = [0u64; 256];
+            for &v in luma.iter() {
+                hist[v.round().clamp(0.0, 255.0) as usize] += 1;
+            }
+            otsu(&hist)
+        });
+
+        Zip::from(&mut self.image)
+            .and(&luma)
+            .par_for_each(|pixel, &l| {
+                let v = if l >= threshold { 255 } else { 0 };
+                *pixel = Rgba([v, v, v, pixel[3]]);
+            });
+        threshold
+    }
+
+    /// CMYK halftone printed on white. `cells` and `angles` (degrees) are
+    /// given per ink in cyan, magenta, yellow, black order.
+    pub fn halftone(&mut self, screen: Screen, cells: [f64; 4], angles: [f64; 4]) {
+        let (width, height) = (self.width, self.height);
+        let rotations = angles.map(|a| a.to_radians().sin_cos());
+        let source = self.clone();
+
+        let mut output = Array2::from_elem((height, width), Rgba([0, 0, 0, 0]));
+        output.axis_iter_mut(Axis(0))
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(y, mut out_row)| {
+                for (x, out) in out_row.iter_mut().enumerate() {
+                    let (px, py) = (x as f64, y as f64);
+                    let mut inks = [0.0; 4];
+                    for (ink, coverage) in inks.iter_mut().enumerate() {
+                        let cell = cells[ink].max(1.0);
+                        let (sin, cos) = rotations[ink];
+
+                        // position in the rotated screen, in cells
+                        let u = (px * cos + py * sin) / cell;
+                        let v = (-px * sin + py * cos) / cell;
+                        let (cu, cv) = (u.floor() + 0.5, v.floor() + 0.5);
+
+                        // ink amount at the cell center
+                        let (cx, cy) = ((cu * cos - cv * sin) * cell, (cu * sin + cv * cos) * cell);
+                        let Rgba([r, g, b, _]) = source.sample_bilinear(cx, cy, EdgeMode::Clamp);
+                        let amount = rgb_to_cmyk([r, g, b].map(|c| c as f64 / 255.0))[ink];
+
+                        // signed distance to the edge of the dot or line, in pixels
+                        let edge = match screen {
+                            // past half coverage the paper shows as a shrinking
+                            // hole around the cell corner instead, so full ink
+                            // closes up with no gaps between the dots
+                            Screen::Dot if amount <= 0.5 => {
+                                let radius = (amount / std::f64::consts::PI).sqrt() * cell;
+                                radius - (u - cu).hypot(v - cv) * cell
+                            }
+                            Screen::Dot => {
+                                let radius = ((1.0 - amount) / std::f64::consts::PI).sqrt() * cell;
+                                (u - u.round()).hypot(v - v.round()) * cell - radius
+                            }
+                            Screen::Line => amount * cell / 2.0 - (v - cv).abs() * cell,
+                        };
+                        *coverage = (edge + 0.5).clamp(0.0, 1.0);
+                    }
+
+                    let [c, m, ye, k] = inks;
+                    let paper = |ink: f64| ((1.0 - ink) * (1.0 - k) * 255.0).round() as u8;
+                    *out = Rgba([paper(c), paper(m), paper(ye), source.image[[y, x]][3]]);
+                }
+            });
+
+        self.image = output;
+    }
+}
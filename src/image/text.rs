@@ -0,0 +1,226 @@
+use image::Rgba;
+use rustybuzz::{ Face, UnicodeBuffer, ttf_parser };
+
+use super::Image;
+use super::path::Path;
+use super::raster::FillRule;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextStyle {
+    /// Em size in pixels
+    pub size: f64,
+    pub color: Rgba<u8>,
+    /// Extra pixels added after every glyph
+    pub letter_spacing: f64,
+    /// Multiple of the font's own line height
+    pub line_height: f64,
+    pub align: Align,
+    /// Width in pixels lines are wrapped to, `None` only breaks on newlines
+    pub wrap: Option<f64>,
+}
+
+/// Shaped glyph, sizes in pixels
+struct Glyph {
+    id: ttf_parser::GlyphId,
+    x_offset: f64,
+    y_offset: f64,
+    /// Advance including the letter spacing
+    advance: f64,
+    /// Comes from a space, where lines may be broken
+    space: bool,
+}
+
+/// Glyph with its pen position in pixels relative to the line start
+struct Placed {
+    id: ttf_parser::GlyphId,
+    x: f64,
+    y: f64,
+}
+
+struct Line {
+    glyphs: Vec<Placed>,
+    width: f64,
+}
+
+/// Shape a paragraph once with kerning and the font's default ligatures
+fn shape_paragraph(face: &Face, text: &str, scale: f64, style: &TextStyle) -> Vec<Glyph> {
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    let shaped = rustybuzz::shape(face, &[], buffer);
+
+    shaped.glyph_infos().iter().zip(shaped.glyph_positions())
+        .map(|(info, pos)| Glyph {
+            id: ttf_parser::GlyphId(info.glyph_id as u16),
+            x_offset: pos.x_offset as f64 * scale,
+            y_offset: -pos.y_offset as f64 * scale,
+            advance: pos.x_advance as f64 * scale + style.letter_spacing,
+            space: text[info.cluster as usize..].starts_with(' '),
+        })
+        .collect()
+}
+
+/// Place a run of shaped glyphs from the start of a line
+fn place_line(glyphs: &[Glyph], style: &TextStyle) -> Line {
+    let mut pen = 0.0;
+    let placed = glyphs.iter()
+        .map(|g| {
+            let placed = Placed { id: g.id, x: pen + g.x_offset, y: g.y_offset };
+            pen += g.advance;
+            placed
+        })
+        .collect::<Vec<_>>();
+    // spacing only goes between glyphs
+    let width = if placed.is_empty() { 0.0 } else { pen - style.letter_spacing };
+    Line { glyphs: placed, width }
+}
+
+/// Break a shaped paragraph greedily at spaces so every line fits in
+/// `wrap`, a single word wider than that gets a line of its own. Spaces
+/// inside a line are kept, the run a line is broken at is dropped.
+fn wrap_paragraph(glyphs: &[Glyph], style: &TextStyle) -> Vec<Line> {
+    let Some(wrap) = style.wrap else {
+        return vec![place_line(glyphs, style)];
+    };
+
+    let mut lines = Vec::new();
+    let mut start = 0;
+    // last space run in the current line that follows a word
+    let mut last_break = None;
+    let mut pen = 0.0;
+    let mut i = 0;
+    while i < glyphs.len() {
+        let glyph = &glyphs[i];
+        if glyph.space {
+            if i > start && !glyphs[i - 1].space {
+                last_break = Some(i);
+            }
+        } else if pen + glyph.advance - style.letter_spacing > wrap
+            && let Some(end) = last_break.take()
+        {
+            lines.push(place_line(&glyphs[start..end], style));
+            start = end + glyphs[end..].iter().take_while(|g| g.space).count();
+            // measure the word that overflowed again from the new line
+            i = start;
+            pen = 0.0;
+            continue;
+        }
+        pen += glyph.advance;
+        i += 1;
+    }
+    lines.push(place_line(&glyphs[start..], style));
+    lines
+}
+
+/// Collects glyph outlines into a [`Path`], flipping y and placing them
+struct Outline<'a> {
+    path: &'a mut Path,
+    scale: f64,
+    x: f64,
+    y: f64,
+}
+
+impl Outline<'_> {
+    fn point(&self, x: f32, y: f32) -> [f64; 2] {
+        [self.x + x as f64 * self.scale, self.y - y as f64 * self.scale]
+    }
+}
+
+impl ttf_parser::OutlineBuilder for Outline<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.path.move_to(p);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.path.line_to(p);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (c, p) = (self.point(x1, y1), self.point(x, y));
+        self.path.quad_to(c, p);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (c1, c2, p) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.path.cubic_to(c1, c2, p);
+    }
+
+    fn close(&mut self) {
+        self.path.close();
+    }
+}
+
+impl Image {
+    /// Lay out and render `text` with the TrueType/OpenType font at `path`.
+    /// The image is as wide as the longest line, or the wrap width if that
+    /// is wider, and as tall as the lines from the first ascender to the
+    /// last descender, plus whatever ink reaches past that box.
+    pub fn from_text(path: &str, text: &str, style: &TextStyle) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| e.to_string())?;
+        let face = Face::from_slice(&data, 0).ok_or("not a TrueType or OpenType font")?;
+
+        let scale = style.size / face.units_per_em() as f64;
+        let ascender = face.ascender() as f64 * scale;
+        let descender = face.descender() as f64 * scale;
+        let line_advance = (ascender - descender + face.line_gap() as f64 * scale) * style.line_height;
+
+        let lines = text
+            .split('\n')
+            .flat_map(|paragraph| {
+                let glyphs = shape_paragraph(&face, paragraph.trim_end_matches('\r'), scale, style);
+                wrap_paragraph(&glyphs, style)
+            })
+            .collect::<Vec<_>>();
+
+        let widest = lines.iter().map(|l| l.width).fold(0.0, f64::max);
+        let box_width = style.wrap.map_or(widest, |wrap| wrap.max(widest));
+        let box_height = ascender - descender + line_advance * (lines.len() - 1) as f64;
+
+        let mut outline = Path::new();
+        for (i, line) in lines.iter().enumerate() {
+            let baseline = ascender + line_advance * i as f64;
+            let start = match style.align {
+                Align::Left => 0.0,
+                Align::Center => (box_width - line.width) / 2.0,
+                Align::Right => box_width - line.width,
+            };
+            for glyph in &line.glyphs {
+                let mut builder = Outline {
+                    path: &mut outline,
+                    scale,
+                    x: start + glyph.x,
+                    y: baseline + glyph.y,
+                };
+                face.outline_glyph(glyph.id, &mut builder);
+            }
+        }
+
+        // grow the box so overhangs past it, like negative side bearings,
+        // accents above the ascender or italic tails, are not cut off
+        let [left, top, right, bottom] = match outline.bbox() {
+            Some([x, y, w, h]) => [
+                (-x).max(0.0).ceil(),
+                (-y).max(0.0).ceil(),
+                (x + w - box_width).max(0.0),
+                (y + h - box_height).max(0.0),
+            ],
+            None => [0.0; 4],
+        };
+        outline.transform(&[1.0, 0.0, left, 0.0, 1.0, top]);
+
+        let width = (left + box_width + right).ceil().max(0.0) as usize;
+        let height = (top + box_height + bottom).ceil().max(0.0) as usize;
+        let mut image = Image::new(width, height);
+        image.fill_path(&outline, style.color, FillRule::NonZero);
+        Ok(image)
+    }
+}
@@ -0,0 +1,402 @@
+use image::Rgba;
+use ndarray::{
+    parallel::prelude::*,
+    prelude::*,
+};
+
+use super::{ EdgeMode, Image };
+use super::resample::Kernel;
+
+/// Interpolation used when sampling between pixel centers
+#[derive(Clone, Copy, Debug)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    /// One of the resampling kernels used by resizing
+    Kernel(Kernel),
+}
+
+/// Size of the output of a geometric transform
+#[derive(Clone, Copy, Debug)]
+pub enum Bounds {
+    /// Keep the size of the source, whatever is moved outside is cut off
+    Fixed,
+    /// Grow or shrink to exactly fit the transformed image
+    Expand,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Flip {
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+/// Where an image is anchored inside a frame of a different size
+#[derive(Clone, Copy, Debug)]
+pub enum Gravity {
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Gravity {
+    /// Horizontal and vertical anchor, 0 is the start and 1 the end
+    fn anchor(&self) -> (f64, f64) {
+        match self {
+            Gravity::Center => (0.5, 0.5),
+            Gravity::Top => (0.5, 0.0),
+            Gravity::Bottom => (0.5, 1.0),
+            Gravity::Left => (0.0, 0.5),
+            Gravity::Right => (1.0, 0.5),
+            Gravity::TopLeft => (0.0, 0.0),
+            Gravity::TopRight => (1.0, 0.0),
+            Gravity::BottomLeft => (0.0, 1.0),
+            Gravity::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+/// 2x3 affine matrix `[a b c d e f]` mapping `(x, y)` to
+/// `(a x + b y + c, d x + e y + f)`. Coordinates are continuous, the pixel
+/// at `(0, 0)` covers the square from `(0, 0)` to `(1, 1)`.
+pub type Affine = [f64; 6];
+
+/// Inverse of an affine matrix, `None` if it is singular
+pub fn invert_affine(m: &Affine) -> Option<Affine> {
+    let [a, b, c, d, e, f] = *m;
+    let det = a * e - b * d;
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let (ia, ib, id, ie) = (e / det, -b / det, -d / det, a / det);
+    Some([ia, ib, -(ia * c + ib * f), id, ie, -(id * c + ie * f)])
+}
+
+/// `m` applied after `n`
+fn compose(m: &Affine, n: &Affine) -> Affine {
+    let [a, b, c, d, e, f] = *m;
+    let [p, q, r, s, t, u] = *n;
+    [
+        a * p + b * s, a * q + b * t, a * r + b * u + c,
+        d * p + e * s, d * q + e * t, d * r + e * u + f,
+    ]
+}
+
+/// Row-major 3x3 projective matrix
+pub type Homography = [f64; 9];
+
+/// Homography taking the unit square corners (0, 0), (1, 0), (1, 1), (0, 1)
+/// onto the quad `q` in the same order (Heckbert's closed form)
+fn square_to_quad(q: &[[f64; 2]; 4]) -> Homography {
+    let [[x0, y0], [x1, y1], [x2, y2], [x3, y3]] = *q;
+    let (sx, sy) = (x0 - x1 + x2 - x3, y0 - y1 + y2 - y3);
+    if sx.abs() < 1e-12 && sy.abs() < 1e-12 {
+        // parallelogram, the map is affine
+        return [x1 - x0, x3 - x0, x0, y1 - y0, y3 - y0, y0, 0.0, 0.0, 1.0];
+    }
+    let (dx1, dx2, dy1, dy2) = (x1 - x2, x3 - x2, y1 - y2, y3 - y2);
+    let det = dx1 * dy2 - dx2 * dy1;
+    let g = (sx * dy2 - dx2 * sy) / det;
+    let h = (dx1 * sy - sx * dy1) / det;
+    [
+        x1 - x0 + g * x1, x3 - x0 + h * x3, x0,
+        y1 - y0 + g * y1, y3 - y0 + h * y3, y0,
+        g, h, 1.0,
+    ]
+}
+
+/// Inverse through the adjugate, `None` if singular
+fn invert_homography(m: &Homography) -> Option<Homography> {
+    let [a, b, c, d, e, f, g, h, i] = *m;
+    let adj = [
+        e * i - f * h, c * h - b * i, b * f - c * e,
+        f * g - d * i, a * i - c * g, c * d - a * f,
+        d * h - e * g, b * g - a * h, a * e - b * d,
+    ];
+    let det = a * adj[0] + b * adj[3] + c * adj[6];
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    Some(adj.map(|v| v / det))
+}
+
+#[inline(always)]
+fn cross(a: [f64; 2], b: [f64; 2]) -> f64 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+/// Parameters `(s, t)` in `0..=1` of the point `p` inside the bilinear patch
+/// with corners `a` (0, 0), `b` (1, 0), `c` (1, 1) and `d` (0, 1)
+fn inverse_bilinear(p: [f64; 2], a: [f64; 2], b: [f64; 2], c: [f64; 2], d: [f64; 2]) -> Option<(f64, f64)> {
+    const EPS: f64 = 1e-6;
+    let e = [b[0] - a[0], b[1] - a[1]];
+    let f = [d[0] - a[0], d[1] - a[1]];
+    let g = [a[0] - b[0] + c[0] - d[0], a[1] - b[1] + c[1] - d[1]];
+    let h = [p[0] - a[0], p[1] - a[1]];
+
+    let k2 = cross(g, f);
+    let k1 = cross(e, f) + cross(h, g);
+    let k0 = cross(h, e);
+
+    // s from t using whichever axis is better conditioned
+    let solve_s = |t: f64| {
+        let (dx, dy) = (e[0] + g[0] * t, e[1] + g[1] * t);
+        if dx.abs() > dy.abs() { (h[0] - f[0] * t) / dx } else { (h[1] - f[1] * t) / dy }
+    };
+    let inside = |v: f64| (-EPS..=1.0 + EPS).contains(&v);
+
+    let candidates = if k2.abs() < 1e-12 {
+        if k1.abs() < 1e-12 {
+            return None;
+        }
+        [-k0 / k1, f64::NAN]
+    } else {
+        let w = k1 * k1 - 4.0 * k0 * k2;
+        if w < 0.0 {
+            return None;
+        }
+        let w = w.sqrt();
+        [(-k1 - w) / (2.0 * k2), (-k1 + w) / (2.0 * k2)]
+    };
+
+    candidates.into_iter()
+        .filter(|t| inside(*t))
+        .map(|t| (solve_s(t), t))
+        .find(|(s, _)| inside(*s))
+        .map(|(s, t)| (s.clamp(0.0, 1.0), t.clamp(0.0, 1.0)))
+}
+
+/// Smallest size starting at the origin that holds every point
+pub fn fit_points<'a>(points: impl IntoIterator<Item = &'a [f64; 2]>) -> (usize, usize) {
+    points.into_iter().fold((0, 0), |(w, h), p| {
+        (w.max((p[0] - 1e-6).ceil().max(0.0) as usize), h.max((p[1] - 1e-6).ceil().max(0.0) as usize))
+    })
+}
+
+#[inline(always)]
+pub(super) fn apply(m: &Affine, x: f64, y: f64) -> (f64, f64) {
+    (m[0] * x + m[1] * y + m[2], m[3] * x + m[4] * y + m[5])
+}
+
+impl Image {
+    /// Sample at fractional coordinates where integer values are pixel centers
+    #[inline(always)]
+    pub fn sample(&self, x: f64, y: f64, filter: Filter, edge: EdgeMode) -> Rgba<u8> {
+        match filter {
+            Filter::Nearest => self.get_pixel_edge(x.round() as isize, y.round() as isize, edge),
+            Filter::Bilinear => self.sample_bilinear(x, y, edge),
+            Filter::Kernel(kernel) => self.sample_kernel(x, y, kernel, edge),
+        }
+    }
+
+    /// Cut out a rectangle, parts of it outside of the image are transparent
+    pub fn crop(&mut self, x: isize, y: isize, width: usize, height: usize) {
+        let source = &self.image;
+        let (src_width, src_height) = (self.width, self.height);
+        self.image = Array2::from_shape_fn((height, width), |(dy, dx)| {
+            let (sx, sy) = (x + dx as isize, y + dy as isize);
+            if sx < 0 || sy < 0 || sx >= src_width as isize || sy >= src_height as isize {
+                Rgba([0, 0, 0, 0])
+            } else {
+                source[[sy as usize, sx as usize]]
+            }
+        });
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Crop or pad to `width` by `height` keeping the side given by `gravity`,
+    /// padding is transparent
+    pub fn frame(&mut self, width: usize, height: usize, gravity: Gravity) {
+        let (ax, ay) = gravity.anchor();
+        let x = ((self.width as f64 - width as f64) * ax).round() as isize;
+        let y = ((self.height as f64 - height as f64) * ay).round() as isize;
+        self.crop(x, y, width, height);
+    }
+
+    pub fn flip(&mut self, flip: Flip) {
+        match flip {
+            Flip::Horizontal => self.image.invert_axis(Axis(1)),
+            Flip::Vertical => self.image.invert_axis(Axis(0)),
+            Flip::Both => {
+                self.image.invert_axis(Axis(0));
+                self.image.invert_axis(Axis(1));
+            }
+        }
+        self.image = self.image.as_standard_layout().into_owned();
+    }
+
+    /// Rotate by a multiple of 90 degrees clockwise without resampling,
+    /// the width and height are swapped for odd quarter turns
+    pub fn rotate_quarter(&mut self, turns: i64) {
+        match turns.rem_euclid(4) {
+            1 => {
+                self.image.swap_axes(0, 1);
+                self.image.invert_axis(Axis(1));
+            }
+            2 => {
+                self.image.invert_axis(Axis(0));
+                self.image.invert_axis(Axis(1));
+            }
+            3 => {
+                self.image.swap_axes(0, 1);
+                self.image.invert_axis(Axis(0));
+            }
+            _ => return,
+        }
+        self.image = self.image.as_standard_layout().into_owned();
+        (self.height, self.width) = self.image.dim();
+    }
+
+    /// Rotate clockwise by `degrees` around the center of the image,
+    /// quarter turns that keep the output size take the lossless path
+    pub fn rotate(&mut self, degrees: f64, bounds: Bounds, filter: Filter, edge: EdgeMode) {
+        let quarter = degrees / 90.0;
+        if (quarter - quarter.round()).abs() < 1e-9 {
+            let turns = quarter.round() as i64;
+            let odd = turns.rem_euclid(2) == 1;
+            if !odd || self.width == self.height || matches!(bounds, Bounds::Expand) {
+                self.rotate_quarter(turns);
+                return;
+            }
+        }
+
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (cx, cy) = (self.width as f64 / 2.0, self.height as f64 / 2.0);
+        let to_origin = [1.0, 0.0, -cx, 0.0, 1.0, -cy];
+        let rotation = [cos, -sin, 0.0, sin, cos, 0.0];
+        let back = [1.0, 0.0, cx, 0.0, 1.0, cy];
+        let matrix = compose(&back, &compose(&rotation, &to_origin));
+        self.affine(&matrix, bounds, filter, edge)
+            .expect("rotation matrices are invertible");
+    }
+
+    /// Scale by `(sx, sy)` keeping the pivot `(px, py)` in place, the image
+    /// keeps its size
+    pub fn scale(&mut self, sx: f64, sy: f64, px: f64, py: f64, filter: Filter, edge: EdgeMode)
+        -> Result<(), String>
+    {
+        let matrix = [sx, 0.0, px - sx * px, 0.0, sy, py - sy * py];
+        self.affine(&matrix, Bounds::Fixed, filter, edge)
+    }
+
+    /// Warp the image by an affine matrix, every output pixel is sampled
+    /// from the source through the inverse matrix
+    pub fn affine(&mut self, matrix: &Affine, bounds: Bounds, filter: Filter, edge: EdgeMode)
+        -> Result<(), String>
+    {
+        let inverse = invert_affine(matrix).ok_or("affine matrix is not invertible")?;
+
+        let (width, height, ox, oy) = match bounds {
+            Bounds::Fixed => (self.width, self.height, 0.0, 0.0),
+            Bounds::Expand => {
+                let (w, h) = (self.width as f64, self.height as f64);
+                let corners = [(0.0, 0.0), (w, 0.0), (0.0, h), (w, h)].map(|(x, y)| apply(matrix, x, y));
+                let (mut x0, mut y0) = (f64::INFINITY, f64::INFINITY);
+                let (mut x1, mut y1) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+                for (x, y) in corners {
+                    (x0, y0) = (x0.min(x), y0.min(y));
+                    (x1, y1) = (x1.max(x), y1.max(y));
+                }
+                // tolerance so exact fits are not grown by rounding noise
+                let (x0, y0) = ((x0 + 1e-6).floor(), (y0 + 1e-6).floor());
+                let (x1, y1) = ((x1 - 1e-6).ceil(), (y1 - 1e-6).ceil());
+                ((x1 - x0).max(0.0) as usize, (y1 - y0).max(0.0) as usize, x0, y0)
+            }
+        };
+
+        self.warp(width, height, |x, y| {
+            let (u, v) = apply(&inverse, x + ox + 0.5, y + oy + 0.5);
+            Some((u - 0.5, v - 0.5))
+        }, filter, edge);
+        Ok(())
+    }
+
+    /// Map the image onto the quad `corners` (top left, top right, bottom
+    /// right, bottom left) of a new `width` by `height` image
+    pub fn perspective(
+        &mut self,
+        corners: &[[f64; 2]; 4],
+        width: usize,
+        height: usize,
+        filter: Filter,
+        edge: EdgeMode,
+    ) -> Result<(), String> {
+        let to_quad = square_to_quad(corners);
+        let inverse = invert_homography(&to_quad).ok_or("corners do not form a quad")?;
+        let (src_width, src_height) = (self.width as f64, self.height as f64);
+
+        self.warp(width, height, |x, y| {
+            let (px, py) = (x + 0.5, y + 0.5);
+            let w = inverse[6] * px + inverse[7] * py + inverse[8];
+            // points on the far side of the horizon have no source
+            if w <= 0.0 {
+                return None;
+            }
+            let u = (inverse[0] * px + inverse[1] * py + inverse[2]) / w;
+            let v = (inverse[3] * px + inverse[4] * py + inverse[5]) / w;
+            Some((u * src_width - 0.5, v * src_height - 0.5))
+        }, filter, edge);
+        Ok(())
+    }
+
+    /// Warp with a lattice of control points. `lattice` holds where each
+    /// point of an evenly spaced grid over the image ends up in a new `width`
+    /// by `height` image, cells in between are bilinear patches.
+    pub fn mesh_warp(
+        &mut self,
+        lattice: &Array2<[f64; 2]>,
+        width: usize,
+        height: usize,
+        filter: Filter,
+        edge: EdgeMode,
+    ) {
+        let (rows, cols) = (lattice.nrows() - 1, lattice.ncols() - 1);
+        let cell_width = self.width as f64 / cols as f64;
+        let cell_height = self.height as f64 / rows as f64;
+
+        // destination bounds of every cell, to skip cells a row cannot hit
+        let cells = Array2::from_shape_fn((rows, cols), |(r, c)| {
+            let quad = [lattice[[r, c]], lattice[[r, c + 1]], lattice[[r + 1, c + 1]], lattice[[r + 1, c]]];
+            let (mut x0, mut y0, mut x1, mut y1) = (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+            for [x, y] in quad {
+                (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+            }
+            (quad, [x0, y0, x1, y1])
+        });
+
+        let mut output = Array2::from_elem((height, width), Rgba([0, 0, 0, 0]));
+        output.axis_iter_mut(Axis(0))
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(y, mut out_row)| {
+                let py = y as f64 + 0.5;
+                for ((r, c), ([a, b, cc, d], [x0, y0, x1, y1])) in cells.indexed_iter() {
+                    if py < *y0 || py > *y1 {
+                        continue;
+                    }
+                    let start = (x0 - 0.5).ceil().max(0.0) as usize;
+                    let end = ((x1 - 0.5).floor() + 1.0).clamp(0.0, width as f64) as usize;
+                    for x in start..end {
+                        let px = x as f64 + 0.5;
+                        if let Some((s, t)) = inverse_bilinear([px, py], *a, *b, *cc, *d) {
+                            let u = (c as f64 + s) * cell_width;
+                            let v = (r as f64 + t) * cell_height;
+                            out_row[x] = self.sample(u - 0.5, v - 0.5, filter, edge);
+                        }
+                    }
+                }
+            });
+
+        self.image = output;
+        self.width = width;
+        self.height = height;
+    }
+}
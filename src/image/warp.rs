@@ -0,0 +1,152 @@
+use std::f64::consts::{ FRAC_PI_2, TAU };
+
+use image::Rgba;
+use ndarray::{
+    parallel::prelude::*,
+    prelude::*,
+};
+
+use super::{ EdgeMode, Image };
+use super::transform::Filter;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Ripple {
+    /// Rows shift sideways following a sine wave
+    Horizontal,
+    /// Columns shift up and down following a sine wave
+    Vertical,
+    Both,
+    /// Concentric waves moving samples towards and away from the center
+    Radial,
+}
+
+impl Image {
+    /// Inverse mapping warp into a new `width` by `height` image. `map` takes
+    /// an output pixel center and returns where to sample the source, both
+    /// with integer values at pixel centers. `None` leaves the pixel empty.
+    pub fn warp<F>(&mut self, width: usize, height: usize, map: F, filter: Filter, edge: EdgeMode)
+    where
+        F: Fn(f64, f64) -> Option<(f64, f64)> + Sync,
+    {
+        let mut output = Array2::from_elem((height, width), Rgba([0, 0, 0, 0]));
+        output.axis_iter_mut(Axis(0))
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(y, mut out_row)| {
+                for (x, out) in out_row.iter_mut().enumerate() {
+                    if let Some((u, v)) = map(x as f64, y as f64) {
+                        *out = self.sample(u, v, filter, edge);
+                    }
+                }
+            });
+
+        self.image = output;
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Rotate around `(cx, cy)` by `degrees` at the center, fading out to no
+    /// rotation at `radius`
+    pub fn twirl(&mut self, degrees: f64, radius: f64, cx: f64, cy: f64, filter: Filter, edge: EdgeMode) {
+        let angle = degrees.to_radians();
+        let (cx, cy) = (cx - 0.5, cy - 0.5);
+        self.warp(self.width, self.height, |x, y| {
+            let (dx, dy) = (x - cx, y - cy);
+            let d = dx.hypot(dy);
+            if d >= radius {
+                return Some((x, y));
+            }
+            let (sin, cos) = (angle * (1.0 - d / radius).powi(2)).sin_cos();
+            Some((cx + dx * cos - dy * sin, cy + dx * sin + dy * cos))
+        }, filter, edge);
+    }
+
+    /// Sine wave displacement of `amplitude` pixels, `wavelength` pixels long
+    pub fn ripple(&mut self, amplitude: f64, wavelength: f64, mode: Ripple, filter: Filter, edge: EdgeMode) {
+        let k = TAU / wavelength;
+        let (cx, cy) = (self.width as f64 / 2.0 - 0.5, self.height as f64 / 2.0 - 0.5);
+        self.warp(self.width, self.height, |x, y| {
+            Some(match mode {
+                Ripple::Horizontal => (x + amplitude * (y * k).sin(), y),
+                Ripple::Vertical => (x, y + amplitude * (x * k).sin()),
+                Ripple::Both => (x + amplitude * (y * k).sin(), y + amplitude * (x * k).sin()),
+                Ripple::Radial => {
+                    let (dx, dy) = (x - cx, y - cy);
+                    let d = dx.hypot(dy);
+                    if d < 1e-9 {
+                        return Some((x, y));
+                    }
+                    let s = 1.0 + amplitude * (d * k).sin() / d;
+                    (cx + dx * s, cy + dy * s)
+                }
+            })
+        }, filter, edge);
+    }
+
+    /// Wrap the disc of `radius` around `(cx, cy)` onto a sphere. Positive
+    /// amounts up to 1 bulge outwards, negative ones pinch inwards.
+    pub fn spherize(&mut self, amount: f64, radius: f64, cx: f64, cy: f64, filter: Filter, edge: EdgeMode) {
+        let amount = amount.clamp(-1.0, 1.0);
+        let (cx, cy) = (cx - 0.5, cy - 0.5);
+        self.warp(self.width, self.height, |x, y| {
+            let (dx, dy) = (x - cx, y - cy);
+            let d = dx.hypot(dy) / radius;
+            if !(1e-9..1.0).contains(&d) {
+                return Some((x, y));
+            }
+            // distance on the source seen through a hemisphere, or its inverse
+            let lens = if amount >= 0.0 {
+                d + ((d.asin() / FRAC_PI_2) - d) * amount
+            } else {
+                d + ((d * FRAC_PI_2).sin() - d) * -amount
+            };
+            let s = lens / d;
+            Some((cx + dx * s, cy + dy * s))
+        }, filter, edge);
+    }
+
+    /// Bend the image into a disc: the top row is pulled into the center, the
+    /// bottom row becomes the rim and columns run clockwise from the top
+    pub fn polar(&mut self, filter: Filter, edge: EdgeMode) {
+        let (width, height) = (self.width as f64, self.height as f64);
+        let (cx, cy) = (width / 2.0, height / 2.0);
+        let rim = cx.min(cy);
+        self.warp(self.width, self.height, |x, y| {
+            let (dx, dy) = (x + 0.5 - cx, y + 0.5 - cy);
+            let r = dx.hypot(dy);
+            if r > rim {
+                return None;
+            }
+            let theta = dx.atan2(-dy).rem_euclid(TAU);
+            Some((theta / TAU * width - 0.5, r / rim * height - 0.5))
+        }, filter, edge);
+    }
+
+    /// Inverse of [`Image::polar`], unrolls the disc around the center
+    pub fn unpolar(&mut self, filter: Filter, edge: EdgeMode) {
+        let (width, height) = (self.width as f64, self.height as f64);
+        let (cx, cy) = (width / 2.0, height / 2.0);
+        let rim = cx.min(cy);
+        self.warp(self.width, self.height, |x, y| {
+            let theta = (x + 0.5) / width * TAU;
+            let r = (y + 0.5) / height * rim;
+            let (sin, cos) = theta.sin_cos();
+            Some((cx + r * sin - 0.5, cy - r * cos - 0.5))
+        }, filter, edge);
+    }
+
+    /// Offset every pixel by the red (x) and green (y) channels of `map`,
+    /// stretched over the image. Mid grey is no offset, 0 and 255 move
+    /// `amount` pixels either way.
+    pub fn displace(&mut self, map: &Image, amount: (f64, f64), filter: Filter, edge: EdgeMode) {
+        let sx = map.width as f64 / self.width as f64;
+        let sy = map.height as f64 / self.height as f64;
+        self.warp(self.width, self.height, |x, y| {
+            let offset = map.sample_bilinear((x + 0.5) * sx - 0.5, (y + 0.5) * sy - 0.5, EdgeMode::Clamp);
+            let dx = (offset[0] as f64 - 127.5) / 127.5 * amount.0;
+            let dy = (offset[1] as f64 - 127.5) / 127.5 * amount.1;
+            Some((x + dx, y + dy))
+        }, filter, edge);
+    }
+}
+
@@ -1,6 +1,7 @@
 mod parse;
 mod image;
 mod eval;
+mod expr;
 
 use ariadne::{sources, Color, Label, Report, ReportKind};
 use peak_alloc::PeakAlloc;
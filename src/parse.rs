@@ -72,7 +72,7 @@ pub fn lexer<'src>()
             }
         })
         .then(select! {
-            c if (c as char).is_ascii_alphanumeric()
+            c if char::is_ascii_alphanumeric(&c)
             || allowed_sym(c) => () }.repeated()
         )
         .to_slice()
@@ -106,7 +106,9 @@ pub fn lexer<'src>()
         .collect()
 }
 
-pub fn lex(src: &str) -> (Option<Vec<(Token, Span)>>, Vec<Rich<char, Span>>) {
+pub type LexResult<'src> = (Option<Vec<(Token<'src>, Span)>>, Vec<Rich<'src, char, Span>>);
+
+pub fn lex(src: &str) -> LexResult<'_> {
     lexer().parse(src).into_output_errors()
 }
 
@@ -227,10 +229,11 @@ where
         .collect::<Vec<_>>()
 }
 
-pub fn parse<'src>(tks: &'src Vec<(Token<'src>, SimpleSpan)>, eoi: Span)
-    -> (Option<Vec<(List<'src>, SimpleSpan)>>, Vec<Rich<'src, Token<'src>>>) {
+pub type ParseResult<'src> = (Option<Vec<(List<'src>, SimpleSpan)>>, Vec<Rich<'src, Token<'src>>>);
+
+pub fn parse<'src>(tks: &'src [(Token<'src>, SimpleSpan)], eoi: Span) -> ParseResult<'src> {
     parser()
-        .parse(tks.as_slice()
+        .parse(tks
             .map(eoi, |(t, s)| (t, s)),
         )
         .into_output_errors()